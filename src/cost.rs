@@ -0,0 +1,213 @@
+//! Attribution of sampled offsets, e.g. from a sampling profiler, to TS files,
+//! functions and lines.
+//!
+//! Samples in runtime generated code count towards the closest preceding TS
+//! line, as their cost is usually incurred on behalf of it.
+
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::query::OffsetQuery;
+use crate::sourcemap::SourceMap;
+use crate::wasm::WasmModule;
+
+/// A sampled offset with its weight, e.g. a sample count or time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub offset: u32,
+    pub weight: u64,
+}
+
+/// Read samples as `OFFSET [WEIGHT]` per line, the weight defaulting to 1.
+///
+/// Offsets take the forms of [`OffsetQuery`]; blank lines and lines starting
+/// with `#` are skipped.
+pub fn read_samples<R: BufRead>(input: R, module: Option<&WasmModule>) -> anyhow::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("Bad sample on line {}", n + 1);
+        let mut fields = line.split_whitespace();
+        let offset = fields.next().unwrap_or_default().parse::<OffsetQuery>().with_context(bad)?;
+        let weight = match fields.next() {
+            Some(w) => w.parse().map_err(|_| anyhow::anyhow!("Invalid weight '{}'", w)).with_context(bad)?,
+            None => 1,
+        };
+        if fields.next().is_some() {
+            return Err(anyhow::anyhow!("Expected OFFSET [WEIGHT]")).with_context(bad);
+        }
+        samples.push(Sample { offset: offset.resolve(module).with_context(bad)?, weight });
+    }
+    Ok(samples)
+}
+
+/// Weight attributed to one source file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileCost {
+    pub source: String,
+    pub weight: u64,
+}
+
+/// Weight attributed to one function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionCost {
+    /// Function index, if the module is known.
+    pub index: Option<u32>,
+    /// From the module's name section, else from the map's `names`.
+    pub name: Option<String>,
+    pub weight: u64,
+}
+
+/// Weight attributed to one source line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineCost {
+    pub source: String,
+    pub line: u32,
+    pub weight: u64,
+}
+
+/// Sampled weight per file, function and line, each sorted by descending weight.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CostReport {
+    pub samples: usize,
+    pub total_weight: u64,
+    /// Weight of samples in runtime generated code, included in their closest TS line.
+    pub runtime_generated_weight: u64,
+    /// Weight of samples that could not be attributed to any TS line.
+    pub unattributed_weight: u64,
+    pub files: Vec<FileCost>,
+    pub functions: Vec<FunctionCost>,
+    pub lines: Vec<LineCost>,
+}
+
+impl CostReport {
+    pub fn new(map: &SourceMap, module: Option<&WasmModule>, samples: &[Sample]) -> CostReport {
+        let mut report = CostReport {
+            samples: samples.len(),
+            total_weight: 0,
+            runtime_generated_weight: 0,
+            unattributed_weight: 0,
+            files: Vec::new(),
+            functions: Vec::new(),
+            lines: Vec::new(),
+        };
+        let mut files: HashMap<String, u64> = HashMap::new();
+        let mut functions: HashMap<(Option<u32>, Option<String>), u64> = HashMap::new();
+        let mut lines: HashMap<(String, u32), u64> = HashMap::new();
+        // weights are arbitrary user input, totals stick at u64::MAX rather than overflow
+        let add = |total: &mut u64, weight: u64| *total = total.saturating_add(weight);
+
+        for sample in samples {
+            add(&mut report.total_weight, sample.weight);
+            let loc = map.lookup(sample.offset);
+            let (index, name) = match module.and_then(|m| m.function_at(sample.offset)) {
+                Some(f) => (Some(f.index), f.name.clone()),
+                None => (None, loc.as_ref().and_then(|loc| loc.entry.name.clone())),
+            };
+            if index.is_some() || name.is_some() {
+                add(functions.entry((index, name)).or_default(), sample.weight);
+            }
+
+            let Some(loc) = loc else {
+                add(&mut report.unattributed_weight, sample.weight);
+                continue;
+            };
+            if loc.is_unmapped() {
+                add(&mut report.runtime_generated_weight, sample.weight);
+            }
+            let entry = match loc.is_unmapped() {
+                true => loc.closest.as_ref(),
+                false => Some(&loc.entry),
+            };
+            let Some((source, line)) = entry.and_then(|e| Some((e.source.clone()?, e.line?))) else {
+                add(&mut report.unattributed_weight, sample.weight);
+                continue;
+            };
+            add(files.entry(source.clone()).or_default(), sample.weight);
+            add(lines.entry((source, line)).or_default(), sample.weight);
+        }
+
+        report.files = files.into_iter()
+            .map(|(source, weight)| FileCost { source, weight })
+            .collect();
+        report.files.sort_by(|a, b| b.weight.cmp(&a.weight).then_with(|| a.source.cmp(&b.source)));
+        report.functions = functions.into_iter()
+            .map(|((index, name), weight)| FunctionCost { index, name, weight })
+            .collect();
+        report.functions.sort_by(|a, b| b.weight.cmp(&a.weight).then(a.index.cmp(&b.index)).then_with(|| a.name.cmp(&b.name)));
+        report.lines = lines.into_iter()
+            .map(|((source, line), weight)| LineCost { source, line, weight })
+            .collect();
+        report.lines.sort_by(|a, b| {
+            b.weight.cmp(&a.weight).then_with(|| a.source.cmp(&b.source)).then(a.line.cmp(&b.line))
+        });
+        report
+    }
+
+    /// Weight per line of `source`, in line order.
+    pub fn line_weights(&self, source: &str) -> Vec<(u32, u64)> {
+        let mut weights: Vec<(u32, u64)> = self.lines.iter()
+            .filter(|l| l.source == source)
+            .map(|l| (l.line, l.weight))
+            .collect();
+        weights.sort();
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sourcemap::MappingEntry;
+
+    fn entry(gen_offset: u32, line: Option<u32>) -> MappingEntry {
+        MappingEntry {
+            gen_offset,
+            source: line.map(|_| "a.ts".to_string()),
+            line,
+            column: line.map(|_| 0),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn reads_offsets_and_weights() {
+        let input = "# offset weight\n0x10 3\n\n17\n";
+        let samples = read_samples(input.as_bytes(), None).unwrap();
+        assert_eq!(samples, [Sample { offset: 0x10, weight: 3 }, Sample { offset: 17, weight: 1 }]);
+        let err = read_samples("0x10\n0x11 many\n".as_bytes(), None).unwrap_err();
+        assert_eq!(err.to_string(), "Bad sample on line 2");
+    }
+
+    #[test]
+    fn attributes_runtime_generated_code_to_the_closest_line() {
+        let map = SourceMap::from_entries(vec![entry(0x10, Some(1)), entry(0x20, None), entry(0x30, Some(2))]);
+        let samples = [
+            Sample { offset: 0x12, weight: 2 },
+            Sample { offset: 0x22, weight: 3 },
+            Sample { offset: 0x30, weight: 4 },
+            Sample { offset: 0x01, weight: 5 },
+        ];
+        let report = CostReport::new(&map, None, &samples);
+        assert_eq!(report.total_weight, 14);
+        assert_eq!(report.runtime_generated_weight, 3);
+        assert_eq!(report.unattributed_weight, 5);
+        assert_eq!(report.line_weights("a.ts"), [(1, 5), (2, 4)]);
+        assert_eq!(report.files, [FileCost { source: "a.ts".to_string(), weight: 9 }]);
+    }
+
+    #[test]
+    fn weights_saturate() {
+        let map = SourceMap::from_entries(vec![entry(0x10, Some(1))]);
+        let samples = [Sample { offset: 0x10, weight: u64::MAX }, Sample { offset: 0x11, weight: 2 }];
+        let report = CostReport::new(&map, None, &samples);
+        assert_eq!(report.total_weight, u64::MAX);
+        assert_eq!(report.line_weights("a.ts"), [(1, u64::MAX)]);
+    }
+}
@@ -0,0 +1,187 @@
+//! Comparison of the decoded entry tables of two maps, e.g. of two builds.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::reverse::{OffsetRange, ReverseIndex, SourcePosition};
+use crate::sourcemap::{MappingEntry, SourceMap};
+
+/// How the lines with mappings of one source changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CoverageChange {
+    pub source: String,
+    /// Number of lines with mappings in the old and the new map.
+    pub lines_old: usize,
+    pub lines_new: usize,
+    /// Lines mapped only in the new map.
+    pub added: Vec<u32>,
+    /// Lines mapped only in the old map.
+    pub removed: Vec<u32>,
+}
+
+/// Differences between an old and a new map.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MapDiff {
+    pub entries_old: usize,
+    pub entries_new: usize,
+    pub sources_added: Vec<String>,
+    pub sources_removed: Vec<String>,
+    /// Sources present in both maps whose mapped lines differ.
+    pub coverage: Vec<CoverageChange>,
+}
+
+impl MapDiff {
+    pub fn new(old: &SourceMap, new: &SourceMap) -> MapDiff {
+        let lines_old = mapped_lines(old);
+        let lines_new = mapped_lines(new);
+        let sources_old: BTreeSet<&str> = old.sources.iter().map(String::as_str).collect();
+        let sources_new: BTreeSet<&str> = new.sources.iter().map(String::as_str).collect();
+
+        let empty = BTreeSet::new();
+        let coverage = sources_old.intersection(&sources_new).filter_map(|&source| {
+            let lines_old = lines_old.get(source).unwrap_or(&empty);
+            let lines_new = lines_new.get(source).unwrap_or(&empty);
+            let change = CoverageChange {
+                source: source.to_string(),
+                lines_old: lines_old.len(),
+                lines_new: lines_new.len(),
+                added: lines_new.difference(lines_old).copied().collect(),
+                removed: lines_old.difference(lines_new).copied().collect(),
+            };
+            (!change.added.is_empty() || !change.removed.is_empty()).then_some(change)
+        }).collect();
+
+        MapDiff {
+            entries_old: old.entries().len(),
+            entries_new: new.entries().len(),
+            sources_added: sources_new.difference(&sources_old).map(|s| s.to_string()).collect(),
+            sources_removed: sources_old.difference(&sources_new).map(|s| s.to_string()).collect(),
+            coverage,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources_added.is_empty() && self.sources_removed.is_empty() && self.coverage.is_empty()
+    }
+}
+
+fn mapped_lines(map: &SourceMap) -> BTreeMap<&str, BTreeSet<u32>> {
+    let mut lines: BTreeMap<&str, BTreeSet<u32>> = BTreeMap::new();
+    for e in map.entries() {
+        if let (Some(source), Some(line)) = (e.source.as_deref(), e.line) {
+            lines.entry(source).or_default().insert(line);
+        }
+    }
+    lines
+}
+
+/// Where an offset of the old map's module went in the new one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OffsetTranslation {
+    pub offset: u32,
+    /// The TS position of `offset` in the old map; `None` if the offset is
+    /// not mapped, or lies in runtime generated code.
+    pub position: Option<MappingEntry>,
+    /// Whether `ranges` match the position's column, rather than only its line.
+    pub exact: bool,
+    /// Offset ranges of the new module generated from the same position.
+    pub ranges: Vec<OffsetRange>,
+}
+
+/// Translate `offset` of the old map's module to the offsets in the new map
+/// generated from the same TS position, falling back to the same line.
+pub fn translate_offset(old: &SourceMap, new: &ReverseIndex, offset: u32) -> OffsetTranslation {
+    let position = old.lookup(offset)
+        .map(|loc| loc.entry)
+        .filter(|e| e.source.is_some() && e.line.is_some());
+    let Some(entry) = position else {
+        return OffsetTranslation { offset, position: None, exact: false, ranges: Vec::new() };
+    };
+    let mut pos = SourcePosition {
+        source: entry.source.clone().unwrap_or_default(),
+        line: entry.line.unwrap_or_default(),
+        column: entry.column,
+    };
+    let mut ranges = new.lookup(&pos);
+    let exact = !ranges.is_empty();
+    if !exact {
+        pos.column = None;
+        ranges = new.lookup(&pos);
+    }
+    OffsetTranslation { offset, position: Some(entry), exact, ranges }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(gen_offset: u32, source: &str, line: u32, column: u32) -> MappingEntry {
+        MappingEntry { gen_offset, source: Some(source.to_string()), line: Some(line), column: Some(column), name: None }
+    }
+
+    fn old() -> SourceMap {
+        SourceMap::from_entries(vec![
+            entry(10, "a.ts", 1, 0),
+            entry(20, "a.ts", 2, 4),
+            MappingEntry { gen_offset: 30, source: None, line: None, column: None, name: None },
+            entry(40, "b.ts", 1, 0),
+            entry(50, "c.ts", 1, 0),
+        ])
+    }
+
+    fn new() -> SourceMap {
+        SourceMap::from_entries(vec![
+            entry(100, "a.ts", 1, 0),
+            entry(110, "a.ts", 2, 8),
+            entry(120, "a.ts", 3, 0),
+            entry(130, "b.ts", 2, 0),
+            entry(140, "d.ts", 1, 0),
+        ])
+    }
+
+    #[test]
+    fn reports_changed_sources_and_lines() {
+        let diff = MapDiff::new(&old(), &new());
+        assert_eq!((diff.entries_old, diff.entries_new), (5, 5));
+        assert_eq!(diff.sources_added, ["d.ts"]);
+        assert_eq!(diff.sources_removed, ["c.ts"]);
+        assert_eq!(diff.coverage, [
+            CoverageChange { source: "a.ts".to_string(), lines_old: 2, lines_new: 3, added: vec![3], removed: vec![] },
+            CoverageChange { source: "b.ts".to_string(), lines_old: 1, lines_new: 1, added: vec![2], removed: vec![1] },
+        ]);
+        assert!(!diff.is_empty());
+        assert!(MapDiff::new(&old(), &old()).is_empty());
+    }
+
+    #[test]
+    fn translates_offsets_by_column_then_line() {
+        let (old, new) = (old(), new());
+        let index = ReverseIndex::new(&new);
+        let ranges = |t: &OffsetTranslation| -> Vec<(u32, Option<u32>)> { t.ranges.iter().map(|r| (r.start, r.end)).collect() };
+
+        let t = translate_offset(&old, &index, 12);
+        assert_eq!(t.position.as_ref().map(|e| e.gen_offset), Some(10));
+        assert!(t.exact);
+        assert_eq!(ranges(&t), [(100, Some(110))]);
+
+        // no code for a.ts:2:4 in the new map, only for other columns of the line
+        let t = translate_offset(&old, &index, 25);
+        assert!(!t.exact);
+        assert_eq!(ranges(&t), [(110, Some(120))]);
+
+        let t = translate_offset(&old, &index, 55);
+        assert!(t.position.is_some());
+        assert!(!t.exact);
+        assert!(t.ranges.is_empty());
+    }
+
+    #[test]
+    fn leaves_runtime_generated_code_untranslated() {
+        let (old, new) = (old(), new());
+        let index = ReverseIndex::new(&new);
+        for offset in [5, 30, 35] {
+            let t = translate_offset(&old, &index, offset);
+            assert_eq!(t, OffsetTranslation { offset, position: None, exact: false, ranges: Vec::new() });
+        }
+    }
+}
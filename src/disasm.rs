@@ -0,0 +1,136 @@
+//! Decoding of function bodies into WAT text, one line per instruction.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use crate::wasm::WasmModule;
+
+/// Comments wasmprinter adds to instructions, `;; label = @1` after block
+/// openers and `(;@1;)` after branch depths, redundant with the indentation.
+static LABEL_COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s*;; label = @\d+$| \(;@\d+;\)").unwrap());
+
+/// One decoded instruction of a function body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Instruction {
+    /// Module-absolute offset of the opcode.
+    pub offset: u32,
+    /// Number of enclosing blocks, for indentation.
+    pub depth: u32,
+    /// The instruction in text format, e.g. `i32.load offset=8` or `call $process`.
+    pub text: String,
+}
+
+/// Decode every instruction of the function bodies overlapping `range`, in
+/// offset order. Each body ends with its `end`.
+///
+/// The text is printed by `wasmprinter`, naming functions, locals and labels
+/// from the module's name section.
+pub fn disassemble(module: &WasmModule, range: Range<u32>) -> Result<Vec<Instruction>> {
+    let mut storage = String::new();
+    let lines = wasmprinter::Config::new()
+        .offsets_and_lines(module.bytes(), &mut storage)
+        .context("Failed to disassemble module")?;
+    let bodies: Vec<Range<u32>> = module.functions().iter()
+        .filter(|f| f.body.start < range.end && range.start < f.body.end)
+        .map(|f| f.body.clone())
+        .collect();
+
+    let mut instructions = Vec::new();
+    for (offset, line) in lines {
+        let Some(offset) = offset.map(|o| o as u32) else { continue };
+        if !bodies.iter().any(|body| body.contains(&offset)) {
+            continue;
+        }
+        let text = line.trim_end();
+        let indent = text.len() - text.trim_start().len();
+        let text = match text.trim_start() {
+            // the function header and its locals
+            t if t.starts_with('(') => continue,
+            ")" => "end".to_string(),
+            t => LABEL_COMMENT.replace_all(t, "").into_owned(),
+        };
+        // instructions of the body are nested in `(module (func ...))`
+        let depth = (indent as u32 / 2).saturating_sub(2);
+        instructions.push(Instruction { offset, depth, text });
+    }
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble_wat(wat: &str) -> Vec<(u32, String)> {
+        let module = WasmModule::parse(wat::parse_str(wat).unwrap()).unwrap();
+        let body = module.functions().last().unwrap().body.clone();
+        disassemble(&module, body).unwrap().into_iter().map(|i| (i.depth, i.text)).collect()
+    }
+
+    fn texts(instructions: &[(u32, String)]) -> Vec<&str> {
+        instructions.iter().map(|(_, text)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn names_operators_as_the_text_format() {
+        let instructions = disassemble_wat(r#"(module
+            (memory 1 1 shared)
+            (func
+                (drop (i32.atomic.load offset=8 (i32.const 0)))
+                (drop (memory.atomic.notify (i32.const 0) (i32.const 1)))
+                (drop (i31.get_s (ref.i31 (i32.const 3))))
+                (drop (memory.grow (i32.const 1)))
+                (drop (i32.load8_u offset=3 align=1 (i32.const 0)))))"#);
+        assert_eq!(texts(&instructions), [
+            "i32.const 0", "i32.atomic.load offset=8", "drop",
+            "i32.const 0", "i32.const 1", "memory.atomic.notify", "drop",
+            "i32.const 3", "ref.i31", "i31.get_s", "drop",
+            "i32.const 1", "memory.grow", "drop",
+            "i32.const 0", "i32.load8_u offset=3", "drop",
+            "end",
+        ]);
+    }
+
+    #[test]
+    fn names_called_functions_including_imports() {
+        let instructions = disassemble_wat(r#"(module
+            (import "env" "abort" (func $abort))
+            (func $process)
+            (func (call $abort) (call $process) (call 1)))"#);
+        assert_eq!(texts(&instructions), ["call $abort", "call $process", "call $process", "end"]);
+    }
+
+    #[test]
+    fn indents_nested_blocks() {
+        let instructions = disassemble_wat(r#"(module
+            (tag $e)
+            (func
+                (block (br_if 0 (i32.const 1)))
+                try
+                    nop
+                catch $e
+                catch_all
+                    (loop (br 0))
+                end))"#);
+        assert_eq!(instructions, [
+            (0, "block".to_string()), (1, "i32.const 1".to_string()), (1, "br_if 0".to_string()), (0, "end".to_string()),
+            (0, "try".to_string()), (1, "nop".to_string()),
+            (0, "catch $e".to_string()), (0, "catch_all".to_string()),
+            (1, "loop".to_string()), (2, "br 0".to_string()), (1, "end".to_string()),
+            (0, "end".to_string()), (0, "end".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn covers_only_bodies_overlapping_the_range() {
+        let module = WasmModule::parse(wat::parse_str("(module (func nop) (func unreachable) (func drop))").unwrap()).unwrap();
+        let [first, second, third] = module.functions() else { panic!() };
+        let instructions = disassemble(&module, second.body.start..second.body.start + 1).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert!(instructions.iter().all(|i| second.body.contains(&i.offset)));
+        let instructions = disassemble(&module, first.body.start..third.body.start).unwrap();
+        assert_eq!(instructions.len(), 4);
+    }
+}
@@ -0,0 +1,404 @@
+//! Reading of DWARF debug info embedded in a module (`.debug_line`,
+//! `.debug_info` and friends), as an alternative to a JSON source map.
+//!
+//! Addresses in Wasm DWARF are offsets into the contents of the code section;
+//! they are converted to module-absolute offsets like those of source maps.
+//! Addresses past the code section, e.g. the tombstones of discarded
+//! functions, are ignored.
+
+use anyhow::{Context, Result};
+use gimli::{AttributeValue, EndianSlice, LittleEndian, UnitRef};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::sourcemap::MappingEntry;
+use crate::wasm::WasmModule;
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// A call inlined at an offset, from a `DW_TAG_inlined_subroutine`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InlinedCall {
+    /// The inlined function.
+    pub function: Option<String>,
+    /// Where the function was called from, in its caller.
+    pub call_source: Option<String>,
+    /// 1-based line of the call.
+    pub call_line: Option<u32>,
+    /// 0-based column of the call.
+    pub call_column: Option<u32>,
+}
+
+/// The code generated for an inlined call.
+#[derive(Debug, Clone)]
+pub(crate) struct InlinedRange {
+    pub range: Range<u32>,
+    /// Nesting depth of the DIE; deeper calls are inlined into shallower ones.
+    pub depth: isize,
+    pub call: InlinedCall,
+}
+
+/// Line table rows and inlined calls of a module.
+pub(crate) struct DwarfTables {
+    /// Sorted by offset, named after the innermost (possibly inlined) function.
+    pub entries: Vec<MappingEntry>,
+    pub inlined: Vec<InlinedRange>,
+}
+
+/// Whether the module carries DWARF line tables.
+pub fn has_dwarf(module: &WasmModule) -> bool {
+    module.custom_section(".debug_line").is_some()
+}
+
+/// A function or inlined call covering some code, for naming entries.
+struct Scope {
+    range: Range<u32>,
+    depth: isize,
+    name: Option<String>,
+}
+
+pub(crate) fn read_dwarf(module: &WasmModule) -> Result<DwarfTables> {
+    let code = module.code_section()
+        .ok_or_else(|| anyhow::anyhow!("Module has no code section"))?;
+    let load = |id: gimli::SectionId| -> Result<Reader<'_>, gimli::Error> {
+        Ok(EndianSlice::new(module.custom_section(id.name()).unwrap_or(&[]), LittleEndian))
+    };
+    let dwarf = gimli::Dwarf::load(load)?;
+    let to_offset = |address: u64| -> Option<u32> {
+        (address < code.len() as u64).then(|| code.start + address as u32)
+    };
+
+    let mut entries: Vec<MappingEntry> = Vec::new();
+    let mut scopes: Vec<Scope> = Vec::new();
+    let mut inlined: Vec<InlinedRange> = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let unit = unit.unit_ref(&dwarf);
+        let mut files = FileNames::default();
+        read_lines(unit, &to_offset, &mut files, &mut entries)
+            .context("Malformed .debug_line")?;
+        read_scopes(unit, &to_offset, &mut files, &mut scopes, &mut inlined)
+            .context("Malformed .debug_info")?;
+    }
+
+    // of several rows at one address, the last one with a line wins
+    entries.sort_by_key(|e| e.gen_offset);
+    let mut deduped: Vec<MappingEntry> = Vec::with_capacity(entries.len());
+    for e in entries {
+        match deduped.last_mut() {
+            Some(last) if last.gen_offset == e.gen_offset => {
+                if e.source.is_some() || last.source.is_none() {
+                    *last = e;
+                }
+            }
+            _ => deduped.push(e),
+        }
+    }
+    name_entries(&mut deduped, scopes);
+
+    inlined.sort_by_key(|r| (r.range.start, r.depth));
+    Ok(DwarfTables { entries: deduped, inlined })
+}
+
+/// Cache of the full paths of a unit's line program files.
+#[derive(Default)]
+struct FileNames {
+    paths: HashMap<u64, Option<String>>,
+}
+
+impl FileNames {
+    fn get(&mut self, unit: UnitRef<Reader>, index: u64) -> Option<String> {
+        self.paths.entry(index).or_insert_with(|| file_path(unit, index)).clone()
+    }
+}
+
+/// `comp_dir/directory/name` of file `index`; absolute parts replace what precedes them.
+fn file_path(unit: UnitRef<Reader>, index: u64) -> Option<String> {
+    let header = unit.line_program.as_ref()?.header();
+    let file = header.file(index)?;
+    let mut path = PathBuf::new();
+    if let Some(dir) = unit.comp_dir {
+        path.push(dir.to_string_lossy().as_ref());
+    }
+    if let Some(dir) = file.directory(header) {
+        path.push(unit.attr_string(dir).ok()?.to_string_lossy().as_ref());
+    }
+    path.push(unit.attr_string(file.path_name()).ok()?.to_string_lossy().as_ref());
+    Some(path.to_string_lossy().into_owned())
+}
+
+fn read_lines(
+    unit: UnitRef<Reader>,
+    to_offset: &impl Fn(u64) -> Option<u32>,
+    files: &mut FileNames,
+    entries: &mut Vec<MappingEntry>,
+) -> Result<()> {
+    let Some(program) = unit.line_program.clone() else { return Ok(()) };
+    let mut rows = program.rows();
+    while let Some((_, row)) = rows.next_row()? {
+        let Some(gen_offset) = to_offset(row.address()) else { continue };
+        let unmapped = MappingEntry { gen_offset, source: None, line: None, column: None, name: None };
+        // line 0 marks compiler generated code
+        let line = row.line().filter(|_| !row.end_sequence());
+        let entry = match line.and_then(|line| u32::try_from(line.get()).ok()) {
+            Some(line) => MappingEntry {
+                source: files.get(unit, row.file_index()),
+                line: Some(line),
+                column: Some(match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => column.get().saturating_sub(1) as u32,
+                }),
+                ..unmapped
+            },
+            None => unmapped,
+        };
+        entries.push(entry);
+    }
+    Ok(())
+}
+
+fn read_scopes(
+    unit: UnitRef<Reader>,
+    to_offset: &impl Fn(u64) -> Option<u32>,
+    files: &mut FileNames,
+    scopes: &mut Vec<Scope>,
+    inlined: &mut Vec<InlinedRange>,
+) -> Result<()> {
+    let mut depth = 0;
+    let mut cursor = unit.entries();
+    while let Some((delta, die)) = cursor.next_dfs()? {
+        depth += delta;
+        let is_inlined = die.tag() == gimli::DW_TAG_inlined_subroutine;
+        if die.tag() != gimli::DW_TAG_subprogram && !is_inlined {
+            continue;
+        }
+        let mut ranges = Vec::new();
+        let mut iter = unit.die_ranges(die)?;
+        while let Some(range) = iter.next()? {
+            if let (Some(start), Some(end)) = (to_offset(range.begin), to_offset(range.end.saturating_sub(1)))
+                && range.begin < range.end
+            {
+                ranges.push(start..end + 1);
+            }
+        }
+        if ranges.is_empty() {
+            continue;
+        }
+
+        let name = die_name(unit, die, 0)?;
+        if is_inlined {
+            let call = InlinedCall {
+                function: name.clone(),
+                call_source: match die.attr_value(gimli::DW_AT_call_file)? {
+                    Some(AttributeValue::FileIndex(index)) => files.get(unit, index),
+                    Some(value) => value.udata_value().and_then(|index| files.get(unit, index)),
+                    None => None,
+                },
+                call_line: udata(die.attr_value(gimli::DW_AT_call_line)?).filter(|&line| line > 0),
+                // DWARF columns are 1-based, 0 meaning unknown
+                call_column: udata(die.attr_value(gimli::DW_AT_call_column)?).and_then(|c| c.checked_sub(1)),
+            };
+            inlined.extend(ranges.iter().map(|range| InlinedRange { range: range.clone(), depth, call: call.clone() }));
+        }
+        scopes.extend(ranges.into_iter().map(|range| Scope { range, depth, name: name.clone() }));
+    }
+    Ok(())
+}
+
+fn udata(value: Option<AttributeValue<Reader>>) -> Option<u32> {
+    value.and_then(|v| v.udata_value()).and_then(|v| u32::try_from(v).ok())
+}
+
+/// The name of a function DIE, following `DW_AT_abstract_origin` and
+/// `DW_AT_specification` within the unit.
+fn die_name<'a>(
+    unit: UnitRef<Reader<'a>>,
+    die: &gimli::DebuggingInformationEntry<Reader<'a>>,
+    hops: usize,
+) -> Result<Option<String>> {
+    if let Some(name) = die.attr_value(gimli::DW_AT_name)? {
+        return Ok(Some(unit.attr_string(name)?.to_string_lossy().into_owned()));
+    }
+    if hops < 4 {
+        for attr in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+            if let Some(AttributeValue::UnitRef(offset)) = die.attr_value(attr)? {
+                return die_name(unit, &unit.entry(offset)?, hops + 1);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Name each entry after the innermost scope containing it.
+fn name_entries(entries: &mut [MappingEntry], mut scopes: Vec<Scope>) {
+    // outer scopes first, so inner ones end up on top of the stack
+    scopes.sort_by_key(|s| (s.range.start, s.depth));
+    let mut scopes = scopes.into_iter().peekable();
+    let mut active: Vec<Scope> = Vec::new();
+    for e in entries.iter_mut().filter(|e| e.source.is_some()) {
+        while let Some(scope) = scopes.next_if(|s| s.range.start <= e.gen_offset) {
+            active.push(scope);
+        }
+        active.retain(|s| s.range.end > e.gen_offset);
+        e.name = active.iter().max_by_key(|s| s.depth).and_then(|s| s.name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sourcemap::SourceMap;
+    use crate::wasm::testing::module_with_custom;
+    use gimli::write::{self, Address, DwarfUnit, EndianVec, LineProgram, LineString, Sections, UnitEntryId};
+    use gimli::{Encoding, Format, LineEncoding};
+
+    /// Address of discarded functions, past any code section.
+    const TOMBSTONE: u64 = 0xffff_fff0;
+
+    fn add_function(dwarf: &mut DwarfUnit, parent: UnitEntryId, tag: gimli::DwTag, range: Range<u64>) -> UnitEntryId {
+        let id = dwarf.unit.add(parent, tag);
+        let die = dwarf.unit.get_mut(id);
+        die.set(gimli::DW_AT_low_pc, write::AttributeValue::Address(Address::Constant(range.start)));
+        die.set(gimli::DW_AT_high_pc, write::AttributeValue::Udata(range.end - range.start));
+        id
+    }
+
+    fn set_name(dwarf: &mut DwarfUnit, id: UnitEntryId, name: &str) {
+        dwarf.unit.get_mut(id).set(gimli::DW_AT_name, write::AttributeValue::String(name.as_bytes().to_vec()));
+    }
+
+    /// Debug sections of `/src/a.rs` compiled into code section addresses 2..30:
+    ///
+    /// - `outer` at 2..30, into which `inlined` is inlined at 10..20 (called
+    ///   from line 7, column 5), into which `deep` is inlined at 12..15
+    ///   (called from line 3, column unknown)
+    /// - lines 1, 2 and 4 at addresses 2, 10 and 12, compiler generated code at 16
+    /// - a discarded function and its rows at a tombstone address
+    fn debug_sections() -> Vec<(&'static str, Vec<u8>)> {
+        let encoding = Encoding { format: Format::Dwarf32, version: 4, address_size: 4 };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let mut program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(b"/src".to_vec()),
+            None,
+            LineString::String(b"a.rs".to_vec()),
+            None,
+        );
+        let dir = program.default_directory();
+        let file = program.add_file(LineString::String(b"a.rs".to_vec()), dir, None);
+        program.begin_sequence(Some(Address::Constant(2)));
+        for (offset, line, column) in [(0, 1, 1), (8, 2, 3), (10, 4, 0), (14, 0, 0)] {
+            let row = program.row();
+            row.address_offset = offset;
+            row.file = file;
+            row.line = line;
+            row.column = column;
+            program.generate_row();
+        }
+        program.end_sequence(28);
+        program.begin_sequence(Some(Address::Constant(TOMBSTONE)));
+        program.row().file = file;
+        program.row().line = 9;
+        program.generate_row();
+        program.end_sequence(4);
+        dwarf.unit.line_program = program;
+
+        let root = dwarf.unit.root();
+        dwarf.unit.get_mut(root).set(gimli::DW_AT_comp_dir, write::AttributeValue::String(b"/src".to_vec()));
+        let abstract_inlined = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+        set_name(&mut dwarf, abstract_inlined, "inlined");
+        let outer = add_function(&mut dwarf, root, gimli::DW_TAG_subprogram, 2..30);
+        set_name(&mut dwarf, outer, "outer");
+        let inlined = add_function(&mut dwarf, outer, gimli::DW_TAG_inlined_subroutine, 10..20);
+        let die = dwarf.unit.get_mut(inlined);
+        die.set(gimli::DW_AT_abstract_origin, write::AttributeValue::UnitRef(abstract_inlined));
+        die.set(gimli::DW_AT_call_file, write::AttributeValue::FileIndex(Some(file)));
+        die.set(gimli::DW_AT_call_line, write::AttributeValue::Udata(7));
+        die.set(gimli::DW_AT_call_column, write::AttributeValue::Udata(5));
+        let deep = add_function(&mut dwarf, inlined, gimli::DW_TAG_inlined_subroutine, 12..15);
+        set_name(&mut dwarf, deep, "deep");
+        let die = dwarf.unit.get_mut(deep);
+        die.set(gimli::DW_AT_call_file, write::AttributeValue::FileIndex(Some(file)));
+        die.set(gimli::DW_AT_call_line, write::AttributeValue::Udata(3));
+        die.set(gimli::DW_AT_call_column, write::AttributeValue::Udata(0));
+        let discarded = add_function(&mut dwarf, root, gimli::DW_TAG_subprogram, TOMBSTONE..TOMBSTONE + 4);
+        set_name(&mut dwarf, discarded, "discarded");
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+        let mut out = Vec::new();
+        sections.for_each(|id, data| {
+            if !data.slice().is_empty() {
+                out.push((id.name(), data.slice().to_vec()));
+            }
+            Ok::<_, gimli::Error>(())
+        }).unwrap();
+        out
+    }
+
+    /// A module with a single body spanning code section addresses 2..44.
+    fn module(custom: &[(&str, &[u8])]) -> WasmModule {
+        let sections = debug_sections();
+        let mut custom: Vec<(&str, &[u8])> = custom.to_vec();
+        custom.extend(sections.iter().map(|(name, data)| (*name, data.as_slice())));
+        module_with_custom(&[], &[&[0x01; 40]], &custom)
+    }
+
+    #[test]
+    fn names_line_rows_after_the_innermost_function() {
+        let module = module(&[]);
+        let code = module.code_section().unwrap().start;
+        let tables = read_dwarf(&module).unwrap();
+        let entries: Vec<_> = tables.entries.iter()
+            .map(|e| (e.gen_offset - code, e.source.as_deref(), e.line, e.column, e.name.as_deref()))
+            .collect();
+        assert_eq!(entries, [
+            (2, Some("/src/a.rs"), Some(1), Some(0), Some("outer")),
+            (10, Some("/src/a.rs"), Some(2), Some(2), Some("inlined")),
+            (12, Some("/src/a.rs"), Some(4), Some(0), Some("deep")),
+            (16, None, None, None, None),
+            (30, None, None, None, None),
+        ]);
+    }
+
+    #[test]
+    fn orders_inlined_calls_innermost_first() {
+        let module = module(&[]);
+        let code = module.code_section().unwrap().start;
+        let sm = SourceMap::from_dwarf(&module).unwrap();
+        let call = |function: &str, line, column| InlinedCall {
+            function: Some(function.to_string()),
+            call_source: Some("/src/a.rs".to_string()),
+            call_line: Some(line),
+            call_column: column,
+        };
+        assert_eq!(sm.inlined_calls(code + 13), [call("deep", 3, None), call("inlined", 7, Some(4))]);
+        assert_eq!(sm.inlined_calls(code + 16), [call("inlined", 7, Some(4))]);
+        assert_eq!(sm.inlined_calls(code + 20), []);
+        assert_eq!(sm.inlined_calls(code + 2), []);
+    }
+
+    #[test]
+    fn ignores_tombstone_addresses() {
+        let module = module(&[]);
+        let tables = read_dwarf(&module).unwrap();
+        assert!(tables.entries.iter().all(|e| module.code_section().unwrap().contains(&e.gen_offset)));
+        assert!(tables.entries.iter().all(|e| e.line != Some(9) && e.name.as_deref() != Some("discarded")));
+        assert!(tables.inlined.iter().all(|r| r.range.end <= module.code_section().unwrap().end));
+    }
+
+    #[test]
+    fn falls_back_to_dwarf_only_without_a_map() {
+        let module = module(&[]);
+        assert_eq!(SourceMap::from_wasm(&module).unwrap().entries().len(), 5);
+        let mut url = vec![31];
+        url.extend_from_slice(b"data:application/json;base64,!!");
+        let module = self::module(&[("sourceMappingURL", &url)]);
+        let err = SourceMap::from_wasm(&module).unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to decode embedded sourceMappingURL data"), "{:#}", err);
+    }
+}
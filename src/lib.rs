@@ -0,0 +1,56 @@
+//! Look up AssemblyScript source positions from WebAssembly binary offsets.
+//!
+//! ```no_run
+//! use wasm_map_lookup::SourceMap;
+//!
+//! let sm = SourceMap::from_file("program.wasm.map")?;
+//! if let Some(loc) = sm.lookup(0x3040) {
+//!     println!("{:?}:{:?}", loc.entry.source, loc.entry.line);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod cost;
+pub mod diff;
+pub mod disasm;
+pub mod dwarf;
+pub mod paths;
+pub mod profile;
+pub mod query;
+pub mod report;
+pub mod reverse;
+pub mod server;
+pub mod snippet;
+pub mod sourcemap;
+pub mod stats;
+pub mod symbolicate;
+pub mod validate;
+pub mod vlq;
+pub mod wasm;
+
+pub use cost::{read_samples, CostReport, FileCost, FunctionCost, LineCost, Sample};
+pub use diff::{translate_offset, CoverageChange, MapDiff, OffsetTranslation};
+pub use disasm::{disassemble, Instruction};
+pub use dwarf::InlinedCall;
+pub use paths::{PathStyle, SourcePaths};
+pub use profile::{symbolicate_profile, ProfileFormat};
+pub use query::{OffsetQuery, RangeQuery};
+pub use report::{DiffReport, FunctionReport, LookupReport, RangeReport, ReverseReport};
+pub use reverse::{LineTable, OffsetRange, ReverseIndex, SourcePosition};
+pub use server::LookupService;
+pub use snippet::render_snippet;
+pub use sourcemap::{Location, MappingEntry, SourceMap};
+pub use stats::{FunctionStats, LineStats, MapStats, SourceStats, UnmappedRun};
+pub use symbolicate::{FrameInfo, Symbolicator};
+pub use validate::{validate, validate_file, Problem, ProblemKind, SegmentPosition};
+pub use vlq::{vlq_decode, vlq_encode, VlqError};
+pub use wasm::{Function, WasmModule};
+
+/// Parse a Wasm offset given in decimal or `0x` hex notation.
+pub fn parse_offset(s: &str) -> Option<u32> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u32::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse::<u32>().ok()
+    }
+}
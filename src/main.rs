@@ -1,183 +1,1080 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use serde::Deserialize;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use wasm_map_lookup::{disassemble, read_samples, render_snippet, symbolicate_profile, translate_offset, validate_file, CostReport, DiffReport, Function, InlinedCall, Instruction, LookupReport, LookupService, MapDiff, MapStats, MappingEntry, OffsetQuery, OffsetRange, OffsetTranslation, PathStyle, ProfileFormat, RangeQuery, RangeReport, ReverseIndex, ReverseReport, SourceMap, SourcePaths, SourcePosition, Symbolicator, WasmModule};
 
 #[derive(Parser, Debug)]
 #[command(about = "Lookup TS source position by WASM binary offset using AS source map")]
-struct Args {
-    /// Path to the .wasm.map JSON file
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    lookup: Args,
+    #[command(flatten)]
+    paths: PathArgs,
+}
+
+/// Options for printed source paths, shared by all commands.
+#[derive(clap::Args, Debug)]
+struct PathArgs {
+    /// Print source paths as written in the map, or resolved against sourceRoot and the map's location
+    #[arg(long, value_enum, default_value_t = PathStyleArg::Raw, global = true)]
+    paths: PathStyleArg,
+    /// Remove PREFIX from the start of source paths (repeatable)
+    #[arg(long, value_name = "PREFIX", global = true)]
+    strip_prefix: Vec<String>,
+    /// Replace a leading FROM of source paths with TO (repeatable)
+    #[arg(long, value_name = "FROM=TO", global = true, value_parser = SourcePaths::parse_path_map)]
+    path_map: Vec<(String, String)>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum PathStyleArg {
+    /// As written in the map's sources
+    Raw,
+    /// Prefixed with sourceRoot and relative to the map's location
+    Resolved,
+}
+
+impl PathArgs {
+    fn source_paths(&self) -> SourcePaths {
+        SourcePaths {
+            style: match self.paths {
+                PathStyleArg::Raw => PathStyle::Raw,
+                PathStyleArg::Resolved => PathStyle::Resolved,
+            },
+            strip_prefixes: self.strip_prefix.clone(),
+            path_maps: self.path_map.clone(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Rewrite the WASM frames of a stack trace with their TS source positions
+    Symbolicate(SymbolicateArgs),
+    /// Interactive prompt for lookups that keeps the map loaded between queries
+    Repl(ReplArgs),
+    /// Answer JSON-RPC lookup requests on stdin/stdout or HTTP, keeping maps loaded
+    Serve(ServeArgs),
+    /// Chain the maps of successive build steps into one map, e.g. wasm-opt's with asc's
+    Compose(ComposeArgs),
+    /// Compare the mappings of two builds and translate offsets from the old to the new one
+    Diff(DiffArgs),
+    /// Report how much of the module maps back to TS, per source, function and line
+    Stats(StatsArgs),
+    /// Strictly check a map and report every problem; exits non-zero if any is found
+    Validate(ValidateArgs),
+    /// Disassemble the function containing an offset, interleaved with the TS lines it maps to
+    Disasm(DisasmArgs),
+    /// Rewrite the WASM frames of a profile (folded stacks, .cpuprofile or pprof) with their TS functions and lines
+    Profile(ProfileArgs),
+    /// Attribute sampled offsets, with optional weights, to TS files, functions and lines
+    Cost(CostArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ComposeArgs {
+    /// Maps from the final module to the one mapping to TS, each mapping the module of the next
+    #[arg(num_args = 2.., required = true)]
+    maps: Vec<String>,
+    /// Write the composed map to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Map (or .wasm module) of the old build
+    old: String,
+    /// Map (or .wasm module) of the new build
+    new: String,
+    /// Offsets in the old build to find in the new one (decimal, 0x hex, or func[N]:OFF with the old module)
+    offsets: Vec<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatsArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    map: String,
+    /// The .wasm module, to also report per function and count bytes up to the end of the code section
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+    /// Number of unmapped runs, lines and functions to list
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    top: usize,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+/// Output format of commands printing a single report.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    /// Human readable text
+    Text,
+    /// One pretty-printed JSON object
+    Json,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    map: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct DisasmArgs {
+    /// Path to the .wasm module, or to its .wasm.map JSON file together with --wasm
+    map: String,
+    /// Offset whose function to disassemble (decimal, 0x hex, func[N]:OFF or code:OFF),
+    /// or START..END to disassemble only the instructions in that range
+    target: String,
+    /// The .wasm module, if MAP is a map file that does not lead to it
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Maps (or .wasm modules) to load, as PATH or KEY=PATH with KEY e.g. a build ID
+    maps: Vec<String>,
+    /// Serve over HTTP on ADDR (e.g. 127.0.0.1:8080, or just a port; loopback only) instead of stdin/stdout
+    #[arg(long, value_name = "ADDR")]
+    http: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
     map: String,
+    /// The .wasm module, to also report the containing function of each offset
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+    /// Print the matched source line with N lines of context around it
+    #[arg(long, value_name = "N")]
+    context: Option<u32>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SymbolicateArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    map: String,
+    /// File containing the stack trace; reads stdin if omitted or '-'
+    trace: Option<String>,
+    /// The .wasm module, to name frames after the functions in its name section
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ProfileArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    map: String,
+    /// File containing the profile; reads stdin if omitted or '-'
+    profile: Option<String>,
+    /// The .wasm module, to name frames after the functions in its name section
+    /// and resolve frames without an offset like wasm-function[N]
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+    /// Format of the profile; detected from its content if omitted
+    #[arg(long, value_enum)]
+    format: Option<ProfileFormatArg>,
+    /// Write the rewritten profile to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CostArgs {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    map: String,
+    /// File of samples, one 'OFFSET [WEIGHT]' per line; reads stdin if omitted or '-'
+    samples: Option<String>,
+    /// The .wasm module, to attribute samples to the functions of its name section
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+    /// Number of files, functions and lines to list
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    top: usize,
+    /// Also print the TS file SOURCE with the weight of each line in the gutter (text output only)
+    #[arg(long, value_name = "SOURCE")]
+    annotate: Option<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ProfileFormatArg {
+    /// Folded stacks, one `frame;frame;frame count` per line
+    Folded,
+    /// Chrome / V8 .cpuprofile JSON
+    Cpuprofile,
+    /// pprof protobuf, gzip compressed or not
+    Pprof,
+}
+
+#[derive(clap::Args, Debug)]
+struct Args {
+    /// Path to the .wasm.map JSON file, or to the .wasm module to locate its map from
+    #[arg(required_unless_present = "maps")]
+    map: Option<String>,
+    /// Chain of maps instead of MAP, from the final module to the one mapping to TS,
+    /// e.g. --map optimized.wasm --map unoptimized.wasm.map (repeatable)
+    #[arg(long = "map", value_name = "MAP")]
+    maps: Vec<String>,
     /// One or more target WASM offsets (decimal or 0x hex). Accepts multiple values.
+    /// With the module available, also func[N]:OFF (relative to the function body),
+    /// code:OFF (relative to the code section) and wasm-function[N]:OFF
+    /// (module-absolute, checked to fall in function N).
+    /// With --reverse, TS source positions as file:line[:column] instead.
+    /// '-' reads whitespace-separated offsets from stdin.
     offsets: Vec<String>,
+    /// Read whitespace-separated offsets from FILE, after those given as arguments
+    #[arg(long, value_name = "FILE")]
+    offsets_file: Option<String>,
+    /// The .wasm module, to also report the containing function of each offset.
+    /// Implied when MAP is itself a .wasm module.
+    #[arg(long, value_name = "FILE")]
+    wasm: Option<String>,
+    /// Reverse lookup: list the WASM offsets generated from the given TS source positions
+    #[arg(long)]
+    reverse: bool,
+    /// List every mapping in START..END (END exclusive, offsets in any of the forms above),
+    /// collapsing consecutive entries from the same TS line (repeatable)
+    #[arg(long, value_name = "START..END", conflicts_with = "reverse")]
+    range: Vec<String>,
+    /// Print the matched source line with N lines of context around it
+    #[arg(long, value_name = "N")]
+    context: Option<u32>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
-#[derive(Debug, Deserialize)]
-#[allow(unused)]
-struct SourceMap {
-    version: u32,
-    sources: Vec<String>,
-    #[serde(default)]
-    names: Vec<String>,
-    mappings: String,
+impl Args {
+    /// The maps to chain and the queries. With `--map`, there is no positional
+    /// MAP, so the first positional argument is a query too.
+    fn maps_and_queries(&self) -> (Vec<String>, Vec<String>) {
+        if self.maps.is_empty() {
+            (self.map.iter().cloned().collect(), self.offsets.clone())
+        } else {
+            (self.maps.clone(), self.map.iter().chain(&self.offsets).cloned().collect())
+        }
+    }
 }
 
-fn parse_offset(s: &str) -> Option<u32> {
-    if s.starts_with("0x") || s.starts_with("0X") {
-        u32::from_str_radix(&s[2..], 16).ok()
-    } else {
-        s.parse::<u32>().ok()
-    }
-}
-
-fn vlq_decode(segment: &str) -> Vec<i32> {
-    let mut result = Vec::new();
-    let mut value = 0i32;
-    let mut shift = 0;
-    for c in segment.chars() {
-        let mut digit = match c {
-            'A'..='Z' => (c as u8 - b'A') as i32,
-            'a'..='z' => (c as u8 - b'a' + 26) as i32,
-            '0'..='9' => (c as u8 - b'0' + 52) as i32,
-            '+' => 62,
-            '/' => 63,
-            _ => continue,
-        };
-        let continuation = (digit & 32) != 0;
-        digit &= 31;
-        value += digit << shift;
-        shift += 5;
-        if !continuation {
-            let sign = if (value & 1) != 0 { -1 } else { 1 };
-            let val = sign * (value >> 1);
-            result.push(val);
-            value = 0;
-            shift = 0;
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human readable text
+    Text,
+    /// A JSON array with one object per query
+    Json,
+    /// Newline-delimited JSON, one object per query
+    Ndjson,
+}
+
+/// Writes one JSON record per query, either as elements of a JSON array or as NDJSON lines.
+struct RecordWriter<W: Write> {
+    out: W,
+    format: Format,
+    count: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(out: W, format: Format) -> RecordWriter<W> {
+        RecordWriter { out, format, count: 0 }
+    }
+
+    fn write<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        match self.format {
+            Format::Json => {
+                let sep = if self.count == 0 { "[\n" } else { ",\n" };
+                let json = serde_json::to_string_pretty(record)?;
+                write!(self.out, "{}  {}", sep, json.replace('\n', "\n  "))?;
+            }
+            Format::Ndjson => writeln!(self.out, "{}", serde_json::to_string(record)?)?,
+            Format::Text => unreachable!("text output is printed directly"),
         }
+        self.count += 1;
+        self.out.flush()?;
+        Ok(())
     }
-    result
-}
 
-#[derive(Debug, Clone)]
-struct MappingEntry {
-    gen_offset: u32,
-    source: Option<String>,
-    line: Option<u32>,
-    column: Option<u32>,
+    fn finish(mut self) -> Result<()> {
+        if self.format == Format::Json {
+            let close = if self.count == 0 { "[]\n" } else { "\n]\n" };
+            write!(self.out, "{}", close)?;
+        }
+        Ok(())
+    }
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let paths = &cli.paths.source_paths();
+    match &cli.command {
+        Some(Command::Symbolicate(args)) => run_symbolicate(args, paths),
+        Some(Command::Repl(args)) => run_repl(args, paths),
+        Some(Command::Serve(args)) => run_serve(args, paths),
+        Some(Command::Compose(args)) => run_compose(args, paths),
+        Some(Command::Diff(args)) => run_diff(args, paths),
+        Some(Command::Stats(args)) => run_stats(args, paths),
+        Some(Command::Validate(args)) => run_validate(args),
+        Some(Command::Disasm(args)) => run_disasm(args, paths),
+        Some(Command::Profile(args)) => run_profile(args, paths),
+        Some(Command::Cost(args)) => run_cost(args, paths),
+        None => run_lookup(&cli.lookup, paths),
+    }
+}
+
+fn run_lookup(args: &Args, paths: &SourcePaths) -> Result<()> {
+    let (maps, offsets) = args.maps_and_queries();
 
-    if args.offsets.is_empty() {
+    if args.reverse {
+        return run_reverse(&maps, &offsets, args, paths);
+    }
+
+    if !args.range.is_empty() {
+        return run_range(&maps, &offsets, args, paths);
+    }
+
+    if offsets.is_empty() && args.offsets_file.is_none() {
         anyhow::bail!("Please provide at least one offset to query (decimal or 0xhex).");
     }
 
-    let target_offsets: Result<Vec<u32>> = args.offsets.iter().map(
-        |s| parse_offset(s).ok_or_else(|| anyhow::anyhow!("Invalid offset"))
-    ).collect();
+    // `-` stands for offsets read from stdin
+    let queries: Result<Vec<Option<OffsetQuery>>> = offsets.iter()
+        .map(|s| if s == "-" { Ok(None) } else { s.parse().map(Some) })
+        .collect();
+    let queries = queries?;
+
+    let (sm, module) = load_inputs(&maps, args.wasm.as_deref(), paths)?;
+
+    // resolve argv offsets up front so a typo fails before any output
+    let target_offsets: Result<Vec<Option<u32>>> = queries.iter()
+        .map(|q| q.map(|q| q.resolve(module.as_ref())).transpose())
+        .collect();
     let target_offsets = target_offsets?;
 
-    let data = fs::read_to_string(&args.map)
-        .with_context(|| format!("Failed to read map file '{}'", &args.map))?;
-    let sm: SourceMap = serde_json::from_str(&data)
-        .with_context(|| format!("Failed to parse JSON from '{}'", &args.map))?;
+    let mut printer = LookupPrinter::new(&sm, module.as_ref(), args);
+    let print_all = || -> Result<()> {
+        for target_offset in target_offsets {
+            match target_offset {
+                Some(offset) => printer.print(offset)?,
+                None => printer.print_stream(io::stdin().lock(), "<stdin>")?,
+            }
+        }
+        if let Some(path) = &args.offsets_file {
+            let file = fs::File::open(path)
+                .with_context(|| format!("Failed to read offsets file '{}'", path))?;
+            printer.print_stream(BufReader::new(file), path)?;
+        }
+        Ok(())
+    };
+    // close the JSON array even if a stream failed midway
+    let printed = print_all();
+    let finished = printer.finish();
+    printed.and(finished)
+}
+
+/// Prints forward lookup results in the selected format as queries arrive.
+struct LookupPrinter<'a> {
+    sm: &'a SourceMap,
+    module: Option<&'a WasmModule>,
+    context: Option<u32>,
+    writer: Option<RecordWriter<io::StdoutLock<'static>>>,
+    /// Number of streamed queries that could not be resolved.
+    bad_queries: usize,
+}
 
-    let mut entries: Vec<MappingEntry> = Vec::new();
+impl<'a> LookupPrinter<'a> {
+    fn new(sm: &'a SourceMap, module: Option<&'a WasmModule>, args: &Args) -> LookupPrinter<'a> {
+        let writer = (args.format != Format::Text)
+            .then(|| RecordWriter::new(io::stdout().lock(), args.format));
+        LookupPrinter { sm, module, context: args.context, writer, bad_queries: 0 }
+    }
 
-    let mut gen_offset = 0u32;
-    let mut source_index = 0i32;
-    let mut original_line = 0i32;
-    let mut original_column = 0i32;
+    fn print(&mut self, offset: u32) -> Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.write(&LookupReport::new(self.sm, offset, self.module)),
+            None => {
+                get_source(self.sm, self.module, offset, self.context);
+                Ok(())
+            }
+        }
+    }
 
-    for line in sm.mappings.split(';') {
-        if line.is_empty() { continue; }
-        for segment in line.split(',') {
-            let fields = vlq_decode(segment);
-            if fields.is_empty() { continue; }
-            let mut idx = 0;
+    /// Look up whitespace-separated offsets from `input`, one line at a time.
+    /// Bad queries are reported on stderr and skipped.
+    fn print_stream<R: BufRead>(&mut self, input: R, name: &str) -> Result<()> {
+        for (n, line) in input.lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read offsets from {}", name))?;
+            for token in line.split_whitespace() {
+                let offset = token.parse::<OffsetQuery>()
+                    .and_then(|q| q.resolve(self.module))
+                    .with_context(|| format!("Bad query on line {} of {}", n + 1, name));
+                match offset {
+                    Ok(offset) => self.print(offset)?,
+                    Err(e) => {
+                        eprintln!("Error: {:#}", e);
+                        self.bad_queries += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminate the output, failing if any streamed query was bad.
+    fn finish(self) -> Result<()> {
+        if let Some(writer) = self.writer {
+            writer.finish()?;
+        }
+        match self.bad_queries {
+            0 => Ok(()),
+            1 => Err(anyhow::anyhow!("1 query could not be resolved")),
+            n => Err(anyhow::anyhow!("{} queries could not be resolved", n)),
+        }
+    }
+}
+
+/// Load the source map and, if available, the module it belongs to.
+///
+/// Given several maps, each maps the module of the next one, and they are
+/// composed into one map from the first module to the sources of the last map.
+fn load_inputs(maps: &[String], wasm: Option<&str>, paths: &SourcePaths) -> Result<(SourceMap, Option<WasmModule>)> {
+    let (mut sm, module) = SourceMap::load_with_module(&maps[0])?;
+    for inner in &maps[1..] {
+        sm = sm.compose(&SourceMap::load(inner)?);
+    }
+    let module = match module {
+        Some(module) => Some(module),
+        None => wasm.map(WasmModule::from_file).transpose()?,
+    };
+    if let (Some(file), Some(module_path)) = (&sm.file, module.as_ref().and_then(|m| m.path())) {
+        let file_name = std::path::Path::new(file).file_name();
+        if file_name.is_some() && file_name != module_path.file_name() {
+            eprintln!("Warning: the map was generated for '{}', not '{}'", file, module_path.display());
+        }
+    }
+    paths.apply(&mut sm);
+    Ok((sm, module))
+}
 
-            // generated column (Wasm offset)
-            gen_offset = gen_offset.wrapping_add(fields[idx] as u32);
-            idx += 1;
+fn run_symbolicate(args: &SymbolicateArgs, paths: &SourcePaths) -> Result<()> {
+    let (sm, module) = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let symbolicator = Symbolicator::new(&sm, module.as_ref());
+    let stdout = io::stdout().lock();
+    match args.trace.as_deref() {
+        None | Some("-") => symbolicator.symbolicate(io::stdin().lock(), stdout)?,
+        Some(path) => {
+            let file = fs::File::open(path)
+                .with_context(|| format!("Failed to read trace file '{}'", path))?;
+            symbolicator.symbolicate(BufReader::new(file), stdout)?;
+        }
+    }
+    Ok(())
+}
 
-            let mut src = None;
-            let mut orig_line = None;
-            let mut orig_col = None;
+fn run_profile(args: &ProfileArgs, paths: &SourcePaths) -> Result<()> {
+    let (sm, module) = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let symbolicator = Symbolicator::new(&sm, module.as_ref());
+    let data = match args.profile.as_deref() {
+        None | Some("-") => {
+            let mut data = Vec::new();
+            io::stdin().lock().read_to_end(&mut data)?;
+            data
+        }
+        Some(path) => fs::read(path).with_context(|| format!("Failed to read profile '{}'", path))?,
+    };
+    let format = match args.format {
+        Some(ProfileFormatArg::Folded) => ProfileFormat::Folded,
+        Some(ProfileFormatArg::Cpuprofile) => ProfileFormat::CpuProfile,
+        Some(ProfileFormatArg::Pprof) => ProfileFormat::Pprof,
+        None => ProfileFormat::detect(&data),
+    };
+    let out = symbolicate_profile(&symbolicator, &data, format)?;
+    match &args.output {
+        Some(path) => fs::write(path, out).with_context(|| format!("Failed to write '{}'", path))?,
+        None => io::stdout().lock().write_all(&out)?,
+    }
+    Ok(())
+}
 
-            if fields.len() >= 4 {
-                source_index += fields[idx]; idx += 1;
-                src = sm.sources.get(source_index as usize).cloned();
+fn run_cost(args: &CostArgs, paths: &SourcePaths) -> Result<()> {
+    let (sm, module) = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let samples = match args.samples.as_deref() {
+        None | Some("-") => read_samples(io::stdin().lock(), module.as_ref())?,
+        Some(path) => {
+            let file = fs::File::open(path)
+                .with_context(|| format!("Failed to read samples file '{}'", path))?;
+            read_samples(BufReader::new(file), module.as_ref())
+                .with_context(|| format!("Failed to read samples file '{}'", path))?
+        }
+    };
+    let report = CostReport::new(&sm, module.as_ref(), &samples);
+    if args.format == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
 
-                original_line += fields[idx]; idx += 1;
-                orig_line = Some((original_line + 1) as u32); // line No. 1-based
+    let total = report.total_weight.max(1);
+    let percent = |weight: u64| weight as f64 * 100.0 / total as f64;
+    println!("Samples: {}, total weight {} ({} in runtime generated code, {} unattributed)",
+        report.samples, report.total_weight, report.runtime_generated_weight, report.unattributed_weight);
+    println!("\nPer file:");
+    for f in report.files.iter().take(args.top) {
+        println!("  {:>8} {:>5.1}%  {}", f.weight, percent(f.weight), f.source);
+    }
+    println!("\nPer function:");
+    for f in report.functions.iter().take(args.top) {
+        let index = f.index.map(|i| format!("[{}] ", i)).unwrap_or_default();
+        println!("  {:>8} {:>5.1}%  {}{}", f.weight, percent(f.weight), index, f.name.as_deref().unwrap_or("(unnamed)"));
+    }
+    println!("\nPer line:");
+    for l in report.lines.iter().take(args.top) {
+        println!("  {:>8} {:>5.1}%  {}:{}", l.weight, percent(l.weight), l.source, l.line);
+    }
 
-                original_column += fields[idx]; // idx += 1;
-                orig_col = Some(original_column as u32);
+    if let Some(source) = &args.annotate {
+        let index = ReverseIndex::new(&sm);
+        let sources = index.resolve_source(source);
+        if sources.is_empty() {
+            anyhow::bail!("No source '{}' in the map", source);
+        }
+        for source in sources {
+            let text = sm.source_text(source)
+                .ok_or_else(|| anyhow::anyhow!("Source text for '{}' not available", source))?;
+            let weights: HashMap<u32, u64> = report.line_weights(source).into_iter().collect();
+            let width = text.lines().count().to_string().len();
+            println!("\n{}:", source);
+            for (i, src) in text.lines().enumerate() {
+                let gutter = match weights.get(&(i as u32 + 1)) {
+                    Some(&w) => format!("{:>8} {:>5.1}%", w, percent(w)),
+                    None => String::new(),
+                };
+                println!("{:>15} | {:>width$} | {}", gutter, i + 1, src);
             }
+        }
+    }
+    Ok(())
+}
 
-            entries.push(MappingEntry {
-                gen_offset,
-                source: src,
-                line: orig_line,
-                column: orig_col,
-            });
+fn run_compose(args: &ComposeArgs, paths: &SourcePaths) -> Result<()> {
+    let (mut sm, _) = load_inputs(&args.maps, None, paths)?;
+    if paths.style == PathStyle::Resolved {
+        // resolved sources already include the root
+        sm.source_root = None;
+    }
+    let json = sm.to_json()?;
+    match &args.output {
+        Some(path) => fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write '{}'", path))?,
+        None => println!("{}", json),
+    }
+    let mapped = sm.entries().iter().filter(|e| e.source.is_some()).count();
+    eprintln!("Composed {} maps: {} entries, {} mapped to sources", args.maps.len(), sm.entries().len(), mapped);
+    Ok(())
+}
+
+fn run_diff(args: &DiffArgs, paths: &SourcePaths) -> Result<()> {
+    let (old, module) = load_inputs(std::slice::from_ref(&args.old), None, paths)?;
+    let (new, _) = load_inputs(std::slice::from_ref(&args.new), None, paths)?;
+    let offsets: Result<Vec<u32>> = args.offsets.iter()
+        .map(|s| s.parse::<OffsetQuery>().and_then(|q| q.resolve(module.as_ref())))
+        .collect();
+
+    let diff = MapDiff::new(&old, &new);
+    let index = ReverseIndex::new(&new);
+    let translations: Vec<OffsetTranslation> = offsets?.into_iter()
+        .map(|offset| translate_offset(&old, &index, offset))
+        .collect();
+
+    if args.format == ReportFormat::Json {
+        let report = DiffReport { diff, offsets: translations };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("Entries: {} -> {}", diff.entries_old, diff.entries_new);
+    for source in &diff.sources_added {
+        println!("+ {}", source);
+    }
+    for source in &diff.sources_removed {
+        println!("- {}", source);
+    }
+    for change in &diff.coverage {
+        println!("~ {}: {} -> {} mapped lines", change.source, change.lines_old, change.lines_new);
+        if !change.added.is_empty() {
+            println!("    added lines: {}", format_lines(&change.added));
+        }
+        if !change.removed.is_empty() {
+            println!("    removed lines: {}", format_lines(&change.removed));
         }
     }
+    if diff.is_empty() {
+        println!("Sources and mapped lines are unchanged");
+    }
 
-    if entries.is_empty() {
-        anyhow::bail!("No mapping entries parsed from 'mappings' field. The map might not include VLQ mappings.");
+    for t in &translations {
+        let Some(entry) = &t.position else {
+            println!("Offset 0x{:x}({}): no TS source in the old build", t.offset, t.offset);
+            continue;
+        };
+        println!("Offset 0x{:x}({}): {}", t.offset, t.offset, format_position(entry));
+        if t.ranges.is_empty() {
+            println!("  not generated from this line in the new build");
+        } else if !t.exact {
+            println!("  column not found in the new build, offsets of the same line:");
+        }
+        for r in &t.ranges {
+            println!("  {}", format_range(r));
+        }
     }
+    Ok(())
+}
 
-    // ascendant
-    entries.sort_by_key(|e| e.gen_offset);
+/// Format sorted line numbers compactly, e.g. `3, 7-9, 12`.
+fn format_lines(lines: &[u32]) -> String {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for &line in lines {
+        match runs.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => runs.push((line, line)),
+        }
+    }
+    runs.iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    for target_offset in target_offsets {
-        get_source(&entries, target_offset);
+fn run_stats(args: &StatsArgs, paths: &SourcePaths) -> Result<()> {
+    let (sm, module) = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let stats = MapStats::new(&sm, module.as_ref(), args.top);
+    if args.format == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
     }
 
+    let total = (stats.mapped_bytes + stats.unmapped_bytes).max(1);
+    let percent = |bytes: u64| bytes as f64 * 100.0 / total as f64;
+    println!("Entries: {} ({} mapped, {} unmapped)", stats.entries, stats.mapped_entries, stats.unmapped_entries);
+    println!("Bytes: {} mapped ({:.1}%), {} unmapped ({:.1}%)",
+        stats.mapped_bytes, percent(stats.mapped_bytes), stats.unmapped_bytes, percent(stats.unmapped_bytes));
+
+    println!("\nBytes per source:");
+    for s in &stats.sources {
+        println!("  {:>8} {:>5.1}%  {} ({} entries)", s.bytes, percent(s.bytes), s.source, s.entries);
+    }
+    if let Some(functions) = &stats.functions {
+        println!("\nLargest functions:");
+        for f in functions.iter().take(args.top) {
+            let mapped = f.mapped_bytes as f64 * 100.0 / f.size.max(1) as f64;
+            println!("  {:>8} {:>5.1}% mapped  [{}] {}", f.size, mapped, f.index, f.name.as_deref().unwrap_or("<unnamed>"));
+        }
+    }
+    println!("\nLargest unmapped runs:");
+    for run in &stats.unmapped_runs {
+        let after = match &run.closest {
+            Some(e) => format!("after {}", format_position(e)),
+            None => "before any TS source".to_string(),
+        };
+        println!("  {:>8}  0x{:x}..0x{:x} {}", run.end - run.start, run.start, run.end, after);
+    }
+    println!("\nLines generating the most bytes:");
+    for line in &stats.top_lines {
+        println!("  {:>8}  {}:{}", line.bytes, line.source, line.line);
+    }
     Ok(())
 }
 
-fn get_source(entries: &Vec<MappingEntry>, target_offset: u32) {
-    // bin search for the biggest offset <= target_offset
-    let idx = match entries.binary_search_by(|e| e.gen_offset.cmp(&target_offset)) {
-        Ok(i) => i,                 // precise
-        Err(0) => {
-            println!("No mapping found <= offset 0x{:x}", target_offset);
-            return;
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let problems = validate_file(&args.map)?;
+    if problems.is_empty() {
+        println!("{}: OK", args.map);
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}: {}", args.map, problem);
+    }
+    anyhow::bail!("{} problem(s) found in '{}'", problems.len(), args.map)
+}
+
+fn run_disasm(args: &DisasmArgs, paths: &SourcePaths) -> Result<()> {
+    let (sm, module) = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let module = module.ok_or_else(|| anyhow::anyhow!(
+        "Disassembly needs the .wasm module, pass it as MAP or with --wasm"
+    ))?;
+    // a single offset shows its whole function, a range only the instructions in it
+    let (range, marked) = if args.target.contains("..") {
+        (args.target.parse::<RangeQuery>()?.resolve(Some(&module))?, None)
+    } else {
+        let offset = args.target.parse::<OffsetQuery>()?.resolve(Some(&module))?;
+        let f = module.function_at(offset)
+            .ok_or_else(|| anyhow::anyhow!("Offset 0x{:x} is not inside a function body", offset))?;
+        (f.body.clone(), Some(offset))
+    };
+
+    let functions: Vec<&Function> = module.functions().iter()
+        .filter(|f| f.body.start < range.end && range.start < f.body.end)
+        .collect();
+    if functions.is_empty() {
+        anyhow::bail!("No function bodies in 0x{:x}..0x{:x}", range.start, range.end);
+    }
+    let all = disassemble(&module, range.clone())?;
+    for (i, f) in functions.into_iter().enumerate() {
+        if i > 0 {
+            println!();
         }
-        Err(i) => i - 1,            // not precise, the one before is that <= target
+        println!("[{}] {} (body 0x{:x}..0x{:x}):", f.index, f.name.as_deref().unwrap_or("(unnamed)"), f.body.start, f.body.end);
+        let instructions: Vec<&Instruction> = all.iter().filter(|ins| f.body.contains(&ins.offset)).collect();
+        let mut line: Option<(Option<String>, Option<u32>)> = None;
+        for (j, ins) in instructions.iter().enumerate() {
+            if !range.contains(&ins.offset) {
+                continue;
+            }
+            if let Some(loc) = sm.lookup(ins.offset) {
+                let key = (loc.entry.source.clone(), loc.entry.line);
+                if line.as_ref() != Some(&key) {
+                    print_source_line(&sm, &loc.entry);
+                    line = Some(key);
+                }
+            }
+            let next = instructions.get(j + 1).map_or(f.body.end, |n| n.offset);
+            let marker = if marked.is_some_and(|o| (ins.offset..next).contains(&o)) { '>' } else { ' ' };
+            println!("{} {:>10}  {:indent$}{}", marker, format!("0x{:x}", ins.offset), "", ins.text, indent = 2 * ins.depth as usize);
+        }
+    }
+    Ok(())
+}
+
+/// The `objdump -S` style source line heading the instructions generated from it.
+fn print_source_line(sm: &SourceMap, e: &MappingEntry) {
+    let (Some(source), Some(line)) = (&e.source, e.line) else {
+        println!("; (internal / runtime generated)");
+        return;
     };
-    let best = entries.get(idx);
-
-    match best {
-        Some(e) => {
-            println!("Query offset: 0x{:x}({}), Best match offset: 0x{:x}({})", target_offset, target_offset, e.gen_offset, e.gen_offset);
-            if e.source.is_none() {
-                // cannot find source, maybe runtime internally generated
-                let prev_ts = entries[..idx].iter().rfind(|prev| prev.source.is_some());
-                println!("Segment: (internal / runtime generated)");
-                if let Some(ts) = prev_ts {
-                    println!(
-                        "Closest TS source before this: {}:{}:{}",
-                        ts.source.as_deref().unwrap_or("(unknown)"),
-                        ts.line.map(|n| n.to_string()).unwrap_or("?".to_string()),
-                        ts.column.map(|n| n.to_string()).unwrap_or("?".to_string())
-                    );
-                } else {
-                    println!("No previous TS source found");
+    println!("; {}:{}", source, line);
+    let text = sm.source_text(source);
+    if let Some(src) = text.as_deref().zip(line.checked_sub(1)).and_then(|(text, i)| text.lines().nth(i as usize)) {
+        println!(";   {}", src.trim_end());
+    }
+}
+
+fn run_serve(args: &ServeArgs, paths: &SourcePaths) -> Result<()> {
+    // a bare port binds to localhost only
+    let addr = args.http.as_ref()
+        .map(|addr| if addr.contains(':') { addr.clone() } else { format!("127.0.0.1:{}", addr) });
+    if let Some(addr) = &addr {
+        LookupService::check_http_addr(addr)?;
+    }
+    let mut service = LookupService::new().with_source_paths(paths.clone());
+    for spec in &args.maps {
+        let (key, path) = spec.split_once('=').unwrap_or((spec, spec));
+        let loaded = service.load(key, path)?;
+        eprintln!("Loaded '{}': {} mapping entries", key, loaded.map.entries().len());
+    }
+    match addr {
+        Some(addr) => {
+            eprintln!("Listening on http://{}", addr);
+            service.serve_http(&addr)
+        }
+        None => Ok(service.serve_stdio(io::stdin().lock(), io::stdout().lock())?),
+    }
+}
+
+const REPL_HELP: &str = "\
+Commands:
+  <OFFSET>...                 look up WASM offsets (decimal, 0x hex, func[N]:OFF, code:OFF, wasm-function[N]:OFF)
+  <FILE:LINE[:COL]>...        reverse lookup of TS source positions
+  :reverse <FILE:LINE[:COL]>  reverse lookup, also for names that look like offsets
+  :context <N>                show N lines of source context (:context off to disable)
+  :reload                     re-read the map (and module) from disk
+  :help                       show this help
+  :quit                       exit (also Ctrl-D)";
+
+/// What the REPL should do after handling a line.
+enum ReplAction {
+    Continue,
+    Reload,
+    Quit,
+}
+
+fn run_repl(args: &ReplArgs, paths: &SourcePaths) -> Result<()> {
+    let mut editor = rustyline::DefaultEditor::new()?;
+    let history = std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".wasm_map_lookup_history"));
+    if let Some(history) = &history {
+        // a missing history file is expected on first use
+        let _ = editor.load_history(history);
+    }
+
+    let mut inputs = load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths)?;
+    let mut context = args.context;
+    println!("Loaded {} mapping entries from '{}'. Type :help for commands.", inputs.0.entries().len(), args.map);
+
+    loop {
+        let (sm, module) = &inputs;
+        let index = ReverseIndex::new(sm);
+
+        let action = loop {
+            let line = match editor.readline("> ") {
+                Ok(line) => line,
+                Err(rustyline::error::ReadlineError::Interrupted) => continue,
+                Err(rustyline::error::ReadlineError::Eof) => break ReplAction::Quit,
+                Err(e) => return Err(e.into()),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line)?;
+            match repl_command(line, sm, module.as_ref(), &index, &mut context) {
+                Ok(ReplAction::Continue) => {}
+                Ok(action) => break action,
+                Err(e) => println!("Error: {:#}", e),
+            }
+        };
+
+        match action {
+            ReplAction::Reload => match load_inputs(std::slice::from_ref(&args.map), args.wasm.as_deref(), paths) {
+                Ok(reloaded) => {
+                    inputs = reloaded;
+                    println!("Reloaded {} mapping entries.", inputs.0.entries().len());
+                }
+                Err(e) => println!("Error: {:#} (keeping the previous map)", e),
+            },
+            ReplAction::Quit | ReplAction::Continue => break,
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+fn repl_command(
+    line: &str,
+    sm: &SourceMap,
+    module: Option<&WasmModule>,
+    index: &ReverseIndex,
+    context: &mut Option<u32>,
+) -> Result<ReplAction> {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some(":q" | ":quit" | ":exit") => return Ok(ReplAction::Quit),
+        Some(":reload") => return Ok(ReplAction::Reload),
+        Some(":help" | ":h") => println!("{}", REPL_HELP),
+        Some(":context") => match words.next() {
+            Some("off") => *context = None,
+            Some(n) => *context = Some(n.parse().with_context(|| format!("Invalid line count '{}'", n))?),
+            None => println!("context: {}", context.map_or("off".to_string(), |n| n.to_string())),
+        },
+        Some(":r" | ":reverse") => {
+            for word in words {
+                get_offsets(index, &word.parse()?);
+            }
+        }
+        Some(cmd) if cmd.starts_with(':') => anyhow::bail!("Unknown command '{}', try :help", cmd),
+        _ => {
+            for word in line.split_whitespace() {
+                match word.parse::<OffsetQuery>() {
+                    Ok(query) => get_source(sm, module, query.resolve(module)?, *context),
+                    Err(e) => match word.parse::<SourcePosition>() {
+                        Ok(pos) => get_offsets(index, &pos),
+                        Err(_) => return Err(e),
+                    },
+                }
+            }
+        }
+    }
+    Ok(ReplAction::Continue)
+}
+
+fn run_range(maps: &[String], offsets: &[String], args: &Args, paths: &SourcePaths) -> Result<()> {
+    if !offsets.is_empty() || args.offsets_file.is_some() {
+        anyhow::bail!("--range cannot be combined with offsets to look up");
+    }
+    let queries: Result<Vec<RangeQuery>> = args.range.iter().map(|s| s.parse()).collect();
+    let queries = queries?;
+
+    let (sm, module) = load_inputs(maps, args.wasm.as_deref(), paths)?;
+    let ranges: Result<Vec<_>> = queries.iter().map(|q| q.resolve(module.as_ref())).collect();
+    let ranges = ranges?;
+
+    if args.format != Format::Text {
+        let mut writer = RecordWriter::new(io::stdout().lock(), args.format);
+        for (query, range) in queries.iter().zip(ranges) {
+            writer.write(&RangeReport::new(query, range, &sm))?;
+        }
+        return writer.finish();
+    }
+
+    for range in ranges {
+        println!("Query range: 0x{:x}..0x{:x}({}..{})", range.start, range.end, range.start, range.end);
+        let runs = sm.line_runs(range.clone());
+        if runs.is_empty() {
+            println!("  No mappings in this range");
+            continue;
+        }
+        if runs[0].start > range.start {
+            println!("  0x{:x}..0x{:x}({}..{}) (no mapping)", range.start, runs[0].start, range.start, runs[0].start);
+        }
+        let mut function = None;
+        for run in &runs {
+            if let Some(module) = &module {
+                let f = module.function_at(run.start).map(|f| f.index);
+                if f != function {
+                    print_function(module, run.start);
+                    function = f;
+                }
+            }
+            if run.entry.source.is_some() {
+                println!("  {}", format_range(run));
+                if let Some(n) = args.context {
+                    print_snippet(&sm, &run.entry, n);
                 }
             } else {
-                println!("Source: {}:{}:{}",
-                    e.source.as_deref().unwrap_or("(no source)"),
-                    e.line.map(|n| n.to_string()).unwrap_or("?".to_string()),
-                    e.column.map(|n| n.to_string()).unwrap_or("?".to_string()),
-                );
+                let end = run.end.unwrap_or(range.end);
+                println!("  0x{:x}..0x{:x}({}..{}) (internal / runtime generated)", run.start, end, run.start, end);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_reverse(maps: &[String], queries: &[String], args: &Args, paths: &SourcePaths) -> Result<()> {
+    if queries.is_empty() {
+        anyhow::bail!("Please provide at least one source position to query (file:line[:column]).");
+    }
+    let positions: Result<Vec<SourcePosition>> = queries.iter().map(|s| s.parse()).collect();
+    let positions = positions?;
+
+    let (sm, _) = load_inputs(maps, args.wasm.as_deref(), paths)?;
+    let index = ReverseIndex::new(&sm);
+
+    if args.format != Format::Text {
+        let mut writer = RecordWriter::new(io::stdout().lock(), args.format);
+        for pos in positions {
+            writer.write(&ReverseReport::new(&pos, index.lookup(&pos)))?;
+        }
+        return writer.finish();
+    }
+
+    for pos in positions {
+        get_offsets(&index, &pos);
+    }
+
+    Ok(())
+}
+
+fn get_offsets(index: &ReverseIndex, pos: &SourcePosition) {
+    let ranges = index.lookup(pos);
+    println!("Query source: {}", pos);
+    if ranges.is_empty() {
+        if index.resolve_source(&pos.source).is_empty() {
+            println!("No source named '{}' in map", pos.source);
+        } else {
+            println!("No WASM offsets generated from this position");
+        }
+        return;
+    }
+    for r in ranges {
+        println!("  {}", format_range(&r));
+    }
+}
+
+fn format_range(r: &OffsetRange) -> String {
+    let range = match r.end {
+        Some(end) => format!("0x{:x}..0x{:x}({}..{})", r.start, end, r.start, end),
+        None => format!("0x{:x}..(end)({}..)", r.start, r.start),
+    };
+    format!("{} {}", range, format_position(&r.entry))
+}
+
+fn format_position(e: &MappingEntry) -> String {
+    let mut s = e.position();
+    if let Some(name) = &e.name {
+        s.push_str(&format!(" (in {})", name));
+    }
+    s
+}
+
+fn print_snippet(sm: &SourceMap, e: &MappingEntry, context: u32) {
+    let (Some(source), Some(line)) = (&e.source, e.line) else { return };
+    match sm.source_text(source) {
+        Some(text) => {
+            if let Some(snippet) = render_snippet(&text, line, e.column, context) {
+                print!("{}", snippet);
             }
         }
-        None => {
-            println!("No mapping found for offset {}", target_offset);
+        None => println!("(source text for '{}' not available)", source),
+    }
+}
+
+fn print_function(module: &WasmModule, offset: u32) {
+    match module.function_at(offset) {
+        Some(f) => println!("Function: [{}] {} +0x{:x}",
+            f.index,
+            f.name.as_deref().unwrap_or("(unnamed)"),
+            offset - f.body.start,
+        ),
+        None => println!("Function: (offset not inside a function body)"),
+    }
+}
+
+fn get_source(sm: &SourceMap, module: Option<&WasmModule>, target_offset: u32, context: Option<u32>) {
+    let Some(loc) = sm.lookup(target_offset) else {
+        println!("No mapping found <= offset 0x{:x}", target_offset);
+        if let Some(module) = module {
+            print_function(module, target_offset);
         }
+        return;
+    };
+    let e = &loc.entry;
+    println!("Query offset: 0x{:x}({}), Best match offset: 0x{:x}({})", target_offset, target_offset, e.gen_offset, e.gen_offset);
+    if let Some(module) = module {
+        print_function(module, target_offset);
     }
+    if loc.is_unmapped() {
+        // cannot find source, maybe runtime internally generated
+        println!("Segment: (internal / runtime generated)");
+        match &loc.closest {
+            Some(ts) => {
+                println!("Closest TS source before this: {}", format_position(ts));
+                if let Some(n) = context {
+                    print_snippet(sm, ts, n);
+                }
+            }
+            None => println!("No previous TS source found"),
+        }
+    } else {
+        println!("Source: {}", format_position(e));
+        for call in sm.inlined_calls(target_offset) {
+            println!("Inlined: {}", format_inlined(&call));
+        }
+        if let Some(n) = context {
+            print_snippet(sm, e, n);
+        }
+    }
+}
+
+fn format_inlined(call: &InlinedCall) -> String {
+    format!("{} called from {}:{}:{}",
+        call.function.as_deref().unwrap_or("(unknown function)"),
+        call.call_source.as_deref().unwrap_or("(no source)"),
+        call.call_line.map(|n| n.to_string()).unwrap_or("?".to_string()),
+        call.call_column.map(|n| n.to_string()).unwrap_or("?".to_string()),
+    )
 }
@@ -0,0 +1,123 @@
+//! How source paths are printed: raw or resolved, with prefixes remapped to
+//! match a local checkout.
+
+use std::collections::HashMap;
+
+use crate::sourcemap::SourceMap;
+
+/// Which form of a source path to print.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    /// As written in the map's `sources`.
+    #[default]
+    Raw,
+    /// Prefixed with `sourceRoot` and relative to the map's location.
+    Resolved,
+}
+
+/// Rewrites source paths, applied in order: style, prefix stripping, remapping.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourcePaths {
+    pub style: PathStyle,
+    /// Leading prefixes to remove; the first that matches is removed.
+    pub strip_prefixes: Vec<String>,
+    /// `(from, to)` replacements of a leading prefix; the first that matches applies.
+    pub path_maps: Vec<(String, String)>,
+}
+
+impl SourcePaths {
+    /// Parse a `FROM=TO` remapping.
+    pub fn parse_path_map(s: &str) -> anyhow::Result<(String, String)> {
+        let (from, to) = s.split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid path map '{}', expected FROM=TO", s))?;
+        Ok((from.to_string(), to.to_string()))
+    }
+
+    /// Whether every path is printed unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.style == PathStyle::Raw && self.strip_prefixes.is_empty() && self.path_maps.is_empty()
+    }
+
+    /// The printed form of `source`, a path in `map`.
+    pub fn rewrite(&self, map: &SourceMap, source: &str) -> String {
+        let mut path = match self.style {
+            PathStyle::Raw => source.to_string(),
+            PathStyle::Resolved => map.resolve_source(source),
+        };
+        if let Some(rest) = self.strip_prefixes.iter().find_map(|p| path.strip_prefix(p.as_str())) {
+            path = rest.to_string();
+        }
+        if let Some((from, to)) = self.path_maps.iter().find(|(from, _)| path.starts_with(from.as_str())) {
+            path = format!("{}{}", to, &path[from.len()..]);
+        }
+        path
+    }
+
+    /// Rewrite all sources of `map` in place.
+    pub fn apply(&self, map: &mut SourceMap) {
+        if self.is_identity() {
+            return;
+        }
+        let renamed: HashMap<String, String> = map.sources.iter()
+            .map(|source| (source.clone(), self.rewrite(map, source)))
+            .collect();
+        map.map_sources(|source| renamed[source].clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> SourceMap {
+        SourceMap::parse(r#"{"version":3,"sourceRoot":"src/","sources":["a.ts","../lib/b.ts","/build/src/c.ts"],
+            "mappings":"AAAA,CCAA,CCAA"}"#).unwrap()
+    }
+
+    fn paths(style: PathStyle, strip_prefixes: &[&str], path_maps: &[&str]) -> SourcePaths {
+        SourcePaths {
+            style,
+            strip_prefixes: strip_prefixes.iter().map(|p| p.to_string()).collect(),
+            path_maps: path_maps.iter().map(|m| SourcePaths::parse_path_map(m).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_path_maps() {
+        assert_eq!(SourcePaths::parse_path_map("/build/=/home/me/").unwrap(), ("/build/".to_string(), "/home/me/".to_string()));
+        assert_eq!(SourcePaths::parse_path_map("a=b=c").unwrap(), ("a".to_string(), "b=c".to_string()));
+        assert!(SourcePaths::parse_path_map("/build/").is_err());
+    }
+
+    #[test]
+    fn rewrites_raw_or_resolved_paths() {
+        let map = map();
+        let raw = SourcePaths::default();
+        assert!(raw.is_identity());
+        assert_eq!(map.sources.iter().map(|s| raw.rewrite(&map, s)).collect::<Vec<_>>(), map.sources);
+        let resolved = paths(PathStyle::Resolved, &[], &[]);
+        assert!(!resolved.is_identity());
+        let sources: Vec<String> = map.sources.iter().map(|s| resolved.rewrite(&map, s)).collect();
+        assert_eq!(sources, ["src/a.ts", "lib/b.ts", "/build/src/c.ts"]);
+    }
+
+    #[test]
+    fn strips_prefixes_before_remapping() {
+        let map = map();
+        let paths = paths(PathStyle::Raw, &["/build/", "/"], &["src/=/home/me/app/src/", "src/lib/=unused/", "lib/=/home/me/lib/"]);
+        // the first matching prefix is stripped, then the first matching map applies
+        assert_eq!(paths.rewrite(&map, "/build/src/c.ts"), "/home/me/app/src/c.ts");
+        assert_eq!(paths.rewrite(&map, "/src/lib/d.ts"), "/home/me/app/src/lib/d.ts");
+        assert_eq!(paths.rewrite(&map, "lib/b.ts"), "/home/me/lib/b.ts");
+        assert_eq!(paths.rewrite(&map, "other/e.ts"), "other/e.ts");
+    }
+
+    #[test]
+    fn applies_to_sources_and_entries() {
+        let mut map = map();
+        paths(PathStyle::Resolved, &["/build/"], &["src/=app/"]).apply(&mut map);
+        assert_eq!(map.sources, ["app/a.ts", "lib/b.ts", "app/c.ts"]);
+        let sources: Vec<Option<&str>> = map.entries().iter().map(|e| e.source.as_deref()).collect();
+        assert_eq!(sources, [Some("app/a.ts"), Some("lib/b.ts"), Some("app/c.ts")]);
+    }
+}
@@ -0,0 +1,535 @@
+//! Rewriting of the Wasm frames of profiles with their TS functions and lines,
+//! so flamegraphs of Wasm code become readable.
+//!
+//! Supported formats:
+//!
+//! - Folded stacks (`frame;frame;frame count`, as consumed by `flamegraph.pl`
+//!   and inferno): each Wasm frame is replaced by `function (file:line)`.
+//! - Chrome / V8 `.cpuprofile` JSON: the `callFrame` of Wasm nodes gets the
+//!   TS function name, file and position; other fields are kept.
+//! - pprof protobuf, gzip compressed or not: lines of Wasm locations point to
+//!   functions named after the TS function and file, with the TS line; the
+//!   original name is kept as the function's system name.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::symbolicate::{runtime_name, FrameInfo, Symbolicator};
+
+/// The format of a profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Folded,
+    CpuProfile,
+    Pprof,
+}
+
+impl ProfileFormat {
+    /// Guess the format from the start of a profile.
+    pub fn detect(data: &[u8]) -> ProfileFormat {
+        if data.starts_with(GZIP_MAGIC) {
+            return ProfileFormat::Pprof;
+        }
+        match data.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => ProfileFormat::CpuProfile,
+            _ if std::str::from_utf8(data).is_err() => ProfileFormat::Pprof,
+            _ => ProfileFormat::Folded,
+        }
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Rewrite `data`, a profile in `format`, returning the rewritten profile.
+pub fn symbolicate_profile(symbolicator: &Symbolicator, data: &[u8], format: ProfileFormat) -> Result<Vec<u8>> {
+    match format {
+        ProfileFormat::Folded => {
+            let mut out = Vec::with_capacity(data.len());
+            symbolicate_folded(symbolicator, data, &mut out)?;
+            Ok(out)
+        }
+        ProfileFormat::CpuProfile => {
+            let text = std::str::from_utf8(data).context("Profile is not UTF-8 text")?;
+            Ok(symbolicate_cpuprofile(symbolicator, text)?.into_bytes())
+        }
+        ProfileFormat::Pprof => symbolicate_pprof(symbolicator, data),
+    }
+}
+
+/// The TS frame for a profile frame, if it is a Wasm frame anything is known about.
+fn resolve(symbolicator: &Symbolicator, frame: &str) -> Option<FrameInfo> {
+    let (offset, name) = symbolicator.frame_offset(frame)?;
+    let info = symbolicator.resolve(offset, name.as_deref());
+    (info.function.is_some() || info.position.is_some()).then_some(info)
+}
+
+/// Rewrite folded stacks line by line; lines that are not `stack count` are
+/// copied unchanged.
+pub fn symbolicate_folded<R: BufRead, W: Write>(symbolicator: &Symbolicator, input: R, mut output: W) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let Some((stack, count)) = line.rsplit_once(' ') else {
+            writeln!(output, "{}", line)?;
+            continue;
+        };
+        let frames: Vec<String> = stack.split(';')
+            .map(|frame| match resolve(symbolicator, frame).and_then(|info| info.label()) {
+                // `;` separates frames, keep it out of the labels
+                Some(label) => label.replace(';', ","),
+                None => frame.to_string(),
+            })
+            .collect();
+        writeln!(output, "{} {}", frames.join(";"), count)?;
+    }
+    Ok(())
+}
+
+/// Rewrite the call frames of a Chrome `.cpuprofile`.
+///
+/// V8 reports Wasm functions with a `wasm://` URL, line 0 and the
+/// module-absolute offset as column; other frames are recognized by their
+/// function name, e.g. `wasm-function[3]`. TS lines and columns are written
+/// 0-based, as the format expects.
+pub fn symbolicate_cpuprofile(symbolicator: &Symbolicator, data: &str) -> Result<String> {
+    let mut profile: Value = serde_json::from_str(data).context("Profile is not valid JSON")?;
+    let nodes = profile.get_mut("nodes").and_then(Value::as_array_mut)
+        .ok_or_else(|| anyhow::anyhow!("Profile has no 'nodes' array"))?;
+    for node in nodes {
+        let Some(frame) = node.get_mut("callFrame").and_then(Value::as_object_mut) else { continue };
+        let name = frame.get("functionName").and_then(Value::as_str).unwrap_or_default().to_string();
+        let url = frame.get("url").and_then(Value::as_str).unwrap_or_default();
+        let column = frame.get("columnNumber").and_then(Value::as_u64).and_then(|c| u32::try_from(c).ok());
+        let info = match column {
+            Some(offset) if url.starts_with("wasm://") => {
+                Some(symbolicator.resolve(offset, runtime_name(&name)))
+            }
+            _ => resolve(symbolicator, &name),
+        };
+        let Some(info) = info else { continue };
+        if let Some(function) = info.function {
+            frame.insert("functionName".to_string(), Value::from(function));
+        }
+        if let Some(e) = info.position
+            && let (Some(source), Some(line)) = (e.source, e.line)
+        {
+            frame.insert("url".to_string(), Value::from(source));
+            frame.insert("lineNumber".to_string(), Value::from(line - 1));
+            frame.insert("columnNumber".to_string(), Value::from(e.column.unwrap_or(0)));
+        }
+    }
+    Ok(serde_json::to_string(&profile)?)
+}
+
+// Field numbers of profile.proto.
+const PROFILE_MAPPING: u32 = 3;
+const PROFILE_LOCATION: u32 = 4;
+const PROFILE_FUNCTION: u32 = 5;
+const PROFILE_STRING_TABLE: u32 = 6;
+const MAPPING_ID: u32 = 1;
+const MAPPING_MEMORY_START: u32 = 2;
+const MAPPING_FILE_OFFSET: u32 = 4;
+const MAPPING_FILENAME: u32 = 5;
+const LOCATION_MAPPING_ID: u32 = 2;
+const LOCATION_ADDRESS: u32 = 3;
+const LOCATION_LINE: u32 = 4;
+const LINE_FUNCTION_ID: u32 = 1;
+const LINE_LINE: u32 = 2;
+const LINE_COLUMN: u32 = 3;
+const FUNCTION_ID: u32 = 1;
+const FUNCTION_NAME: u32 = 2;
+const FUNCTION_SYSTEM_NAME: u32 = 3;
+const FUNCTION_FILENAME: u32 = 4;
+
+/// Rewrite a pprof profile, gzip compressed or not; the output is compressed
+/// if the input was.
+///
+/// A location is taken to be in Wasm code if the name of a function of its
+/// lines is a recognized frame, e.g. `wasm-function[3]:0x1a2b`, or else if
+/// its mapping is a `.wasm` file, in which case its address is translated to
+/// a module offset through the mapping. Locations of a `.wasm` mapping without
+/// lines, i.e. not symbolized by the profiler, get a line added.
+pub fn symbolicate_pprof(symbolicator: &Symbolicator, data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(GZIP_MAGIC) {
+        let mut raw = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut raw).context("Failed to decompress profile")?;
+        let rewritten = symbolicate_pprof(symbolicator, &raw)?;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&rewritten)?;
+        return Ok(encoder.finish()?);
+    }
+
+    let malformed = || "Malformed pprof profile";
+    let mut fields = decode_message(data).with_context(malformed)?;
+    let mut strings: Vec<String> = Vec::new();
+    // id -> (name, memory_start, file_offset)
+    let mut mappings: HashMap<u64, (String, u64, u64)> = HashMap::new();
+    // id -> name
+    let mut functions: HashMap<u64, String> = HashMap::new();
+    let mut max_function_id = 0;
+    for (number, value) in &fields {
+        if *number == PROFILE_STRING_TABLE {
+            strings.push(String::from_utf8_lossy(value.bytes()?).into_owned());
+        }
+    }
+    let string = |index: u64| strings.get(index as usize).cloned().unwrap_or_default();
+    for (number, value) in &fields {
+        match *number {
+            PROFILE_MAPPING => {
+                let mapping = decode_message(value.bytes()?).with_context(malformed)?;
+                mappings.insert(varint(&mapping, MAPPING_ID), (
+                    string(varint(&mapping, MAPPING_FILENAME)),
+                    varint(&mapping, MAPPING_MEMORY_START),
+                    varint(&mapping, MAPPING_FILE_OFFSET),
+                ));
+            }
+            PROFILE_FUNCTION => {
+                let function = decode_message(value.bytes()?).with_context(malformed)?;
+                let id = varint(&function, FUNCTION_ID);
+                max_function_id = max_function_id.max(id);
+                functions.insert(id, string(varint(&function, FUNCTION_NAME)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut table = StringTable { index: HashMap::new(), added: Vec::new(), len: strings.len() as u64 };
+    for (i, s) in strings.iter().enumerate() {
+        table.index.entry(s.clone()).or_insert(i as u64);
+    }
+    // (name, file) -> id of the functions added for TS
+    let mut ts_functions: HashMap<(String, String), u64> = HashMap::new();
+    let mut new_functions: Vec<Vec<u8>> = Vec::new();
+    // point `line` to the TS function and line of `info`, `name` being the profiler's
+    let mut rewrite = |line: &mut Vec<(u32, WireValue)>, info: FrameInfo, name: &str| {
+        let position = info.position.as_ref().filter(|e| e.source.is_some() && e.line.is_some());
+        let function = info.function.clone().unwrap_or_else(|| name.to_string());
+        let file = position.and_then(|e| e.source.clone()).unwrap_or_default();
+        let id = *ts_functions.entry((function.clone(), file.clone())).or_insert_with(|| {
+            max_function_id += 1;
+            new_functions.push(encode_message(&[
+                (FUNCTION_ID, WireValue::Varint(max_function_id)),
+                (FUNCTION_NAME, WireValue::Varint(table.get(&function))),
+                (FUNCTION_SYSTEM_NAME, WireValue::Varint(table.get(name))),
+                (FUNCTION_FILENAME, WireValue::Varint(table.get(&file))),
+            ]));
+            max_function_id
+        });
+        set_varint(line, LINE_FUNCTION_ID, id);
+        if let Some(e) = position {
+            set_varint(line, LINE_LINE, e.line.unwrap_or(0) as u64);
+            set_varint(line, LINE_COLUMN, e.column.unwrap_or(0) as u64);
+        }
+    };
+
+    for (number, value) in fields.iter_mut() {
+        if *number != PROFILE_LOCATION {
+            continue;
+        }
+        let mut location = decode_message(value.bytes()?).with_context(malformed)?;
+        let address = varint(&location, LOCATION_ADDRESS);
+        let mapped = mappings.get(&varint(&location, LOCATION_MAPPING_ID))
+            .filter(|(name, _, _)| name.ends_with(".wasm"))
+            .and_then(|(_, start, file_offset)| {
+                u32::try_from(address.checked_sub(*start)?.checked_add(*file_offset)?).ok()
+            });
+        let mut changed = false;
+        let mut has_lines = false;
+        for (number, line_value) in location.iter_mut() {
+            if *number != LOCATION_LINE {
+                continue;
+            }
+            has_lines = true;
+            let mut line = decode_message(line_value.bytes()?).with_context(malformed)?;
+            let name = functions.get(&varint(&line, LINE_FUNCTION_ID)).cloned().unwrap_or_default();
+            let info = resolve(symbolicator, &name).or_else(|| {
+                mapped.map(|offset| symbolicator.resolve(offset, runtime_name(&name)))
+            });
+            let Some(info) = info else { continue };
+            rewrite(&mut line, info, &name);
+            *line_value = WireValue::Bytes(encode_message(&line));
+            changed = true;
+        }
+        // unsymbolized locations only have an address
+        if !has_lines
+            && let Some(info) = mapped.map(|offset| symbolicator.resolve(offset, None))
+            && (info.function.is_some() || info.position.is_some())
+        {
+            let mut line = Vec::new();
+            rewrite(&mut line, info, "");
+            location.push((LOCATION_LINE, WireValue::Bytes(encode_message(&line))));
+            changed = true;
+        }
+        if changed {
+            *value = WireValue::Bytes(encode_message(&location));
+        }
+    }
+
+    fields.extend(new_functions.into_iter().map(|f| (PROFILE_FUNCTION, WireValue::Bytes(f))));
+    fields.extend(table.added.into_iter().map(|s| (PROFILE_STRING_TABLE, WireValue::Bytes(s.into_bytes()))));
+    Ok(encode_message(&fields))
+}
+
+/// Strings of the profile's `string_table`, plus those added while rewriting.
+struct StringTable {
+    index: HashMap<String, u64>,
+    added: Vec<String>,
+    len: u64,
+}
+
+impl StringTable {
+    fn get(&mut self, s: &str) -> u64 {
+        if let Some(&i) = self.index.get(s) {
+            return i;
+        }
+        let i = self.len;
+        self.len += 1;
+        self.index.insert(s.to_string(), i);
+        self.added.push(s.to_string());
+        i
+    }
+}
+
+/// A field value in the protobuf wire format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WireValue {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(Vec<u8>),
+    Fixed32(u32),
+}
+
+impl WireValue {
+    fn bytes(&self) -> Result<&[u8]> {
+        match self {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            _ => anyhow::bail!("Expected a length-delimited field"),
+        }
+    }
+}
+
+/// The last value of varint field `number`, or 0 (the protobuf default).
+fn varint(fields: &[(u32, WireValue)], number: u32) -> u64 {
+    fields.iter().rev()
+        .find_map(|(n, v)| match v {
+            WireValue::Varint(v) if *n == number => Some(*v),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+fn set_varint(fields: &mut Vec<(u32, WireValue)>, number: u32, value: u64) {
+    fields.retain(|(n, _)| *n != number);
+    fields.push((number, WireValue::Varint(value)));
+}
+
+fn decode_message(mut data: &[u8]) -> Result<Vec<(u32, WireValue)>> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let key = read_varint(&mut data)?;
+        let number = u32::try_from(key >> 3).context("Field number out of range")?;
+        let value = match key & 7 {
+            0 => WireValue::Varint(read_varint(&mut data)?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(take(&mut data, 8)?.try_into()?)),
+            2 => {
+                let len = usize::try_from(read_varint(&mut data)?)?;
+                WireValue::Bytes(take(&mut data, len)?.to_vec())
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(take(&mut data, 4)?.try_into()?)),
+            wire_type => anyhow::bail!("Unsupported wire type {} of field {}", wire_type, number),
+        };
+        fields.push((number, value));
+    }
+    Ok(fields)
+}
+
+fn encode_message(fields: &[(u32, WireValue)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (number, value) in fields {
+        let (wire_type, number) = match value {
+            WireValue::Varint(_) => (0, *number as u64),
+            WireValue::Fixed64(_) => (1, *number as u64),
+            WireValue::Bytes(_) => (2, *number as u64),
+            WireValue::Fixed32(_) => (5, *number as u64),
+        };
+        write_varint(&mut out, number << 3 | wire_type);
+        match value {
+            WireValue::Varint(v) => write_varint(&mut out, *v),
+            WireValue::Fixed64(v) => out.extend_from_slice(&v.to_le_bytes()),
+            WireValue::Bytes(bytes) => {
+                write_varint(&mut out, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            WireValue::Fixed32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+    out
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        anyhow::bail!("Truncated field");
+    }
+    let (head, rest) = data.split_at(len);
+    *data = rest;
+    Ok(head)
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let [byte, rest @ ..] = *data else { anyhow::bail!("Truncated varint") };
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("Varint longer than 10 bytes")
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sourcemap::{MappingEntry, SourceMap};
+
+    fn map() -> SourceMap {
+        let entry = |gen_offset, line, name: &str| MappingEntry {
+            gen_offset,
+            source: Some("assembly/index.ts".to_string()),
+            line: Some(line),
+            column: Some(2),
+            name: Some(name.to_string()),
+        };
+        SourceMap::from_entries(vec![entry(0x46, 2, "calculate"), entry(0x50, 6, "process")])
+    }
+
+    fn message(fields: &[(u32, WireValue)]) -> WireValue {
+        WireValue::Bytes(encode_message(fields))
+    }
+
+    #[test]
+    fn codec_round_trips() {
+        let fields = vec![
+            (1, WireValue::Varint(0)),
+            (2, WireValue::Varint(u64::MAX)),
+            (3, WireValue::Fixed64(0x0102030405060708)),
+            (4, WireValue::Bytes(vec![0xff; 200])),
+            (5, WireValue::Fixed32(7)),
+            (536_870_911, WireValue::Bytes(Vec::new())),
+        ];
+        let encoded = encode_message(&fields);
+        assert_eq!(decode_message(&encoded).unwrap(), fields);
+        assert_eq!(encode_message(&decode_message(&encoded).unwrap()), encoded);
+        assert!(decode_message(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode_message(&[0x0b]).is_err());
+    }
+
+    /// Functions of the rewritten profile per location id, as (name, system name, file, line).
+    fn location_functions(data: &[u8]) -> Vec<Vec<(String, String, String, u64)>> {
+        let fields = decode_message(data).unwrap();
+        let strings: Vec<String> = fields.iter()
+            .filter(|(n, _)| *n == PROFILE_STRING_TABLE)
+            .map(|(_, v)| String::from_utf8(v.bytes().unwrap().to_vec()).unwrap())
+            .collect();
+        let functions: HashMap<u64, Vec<(u32, WireValue)>> = fields.iter()
+            .filter(|(n, _)| *n == PROFILE_FUNCTION)
+            .map(|(_, v)| decode_message(v.bytes().unwrap()).unwrap())
+            .map(|f| (varint(&f, FUNCTION_ID), f))
+            .collect();
+        fields.iter().filter(|(n, _)| *n == PROFILE_LOCATION).map(|(_, v)| {
+            decode_message(v.bytes().unwrap()).unwrap().iter()
+                .filter(|(n, _)| *n == LOCATION_LINE)
+                .map(|(_, line)| {
+                    let line = decode_message(line.bytes().unwrap()).unwrap();
+                    let f = &functions[&varint(&line, LINE_FUNCTION_ID)];
+                    let string = |number| strings[varint(f, number) as usize].clone();
+                    (string(FUNCTION_NAME), string(FUNCTION_SYSTEM_NAME), string(FUNCTION_FILENAME), varint(&line, LINE_LINE))
+                })
+                .collect()
+        }).collect()
+    }
+
+    #[test]
+    fn rewrites_pprof_locations() {
+        let strings = ["", "wasm-function[2]:0x52", "main", "app.wasm", "app.js"];
+        let mut fields: Vec<(u32, WireValue)> = strings.iter()
+            .map(|s| (PROFILE_STRING_TABLE, WireValue::Bytes(s.as_bytes().to_vec())))
+            .collect();
+        fields.extend([
+            (PROFILE_MAPPING, message(&[
+                (MAPPING_ID, WireValue::Varint(1)),
+                (MAPPING_MEMORY_START, WireValue::Varint(0x1000)),
+                (MAPPING_FILE_OFFSET, WireValue::Varint(0x10)),
+                (MAPPING_FILENAME, WireValue::Varint(3)),
+            ])),
+            (PROFILE_MAPPING, message(&[(MAPPING_ID, WireValue::Varint(2)), (MAPPING_FILENAME, WireValue::Varint(4))])),
+            (PROFILE_FUNCTION, message(&[(FUNCTION_ID, WireValue::Varint(1)), (FUNCTION_NAME, WireValue::Varint(1))])),
+            (PROFILE_FUNCTION, message(&[(FUNCTION_ID, WireValue::Varint(2)), (FUNCTION_NAME, WireValue::Varint(2))])),
+            // a Wasm frame recognized by its function name
+            (PROFILE_LOCATION, message(&[(LOCATION_LINE, message(&[(LINE_FUNCTION_ID, WireValue::Varint(1))]))])),
+            // a JS frame
+            (PROFILE_LOCATION, message(&[
+                (LOCATION_MAPPING_ID, WireValue::Varint(2)),
+                (LOCATION_LINE, message(&[(LINE_FUNCTION_ID, WireValue::Varint(2)), (LINE_LINE, WireValue::Varint(9))])),
+            ])),
+            // an unsymbolized address in the module, at offset 0x1038 - 0x1000 + 0x10
+            (PROFILE_LOCATION, message(&[
+                (LOCATION_MAPPING_ID, WireValue::Varint(1)),
+                (LOCATION_ADDRESS, WireValue::Varint(0x1038)),
+            ])),
+            // an address that does not translate to an offset
+            (PROFILE_LOCATION, message(&[
+                (LOCATION_MAPPING_ID, WireValue::Varint(1)),
+                (LOCATION_ADDRESS, WireValue::Varint(0x10)),
+            ])),
+        ]);
+
+        let map = map();
+        let rewritten = symbolicate_pprof(&Symbolicator::new(&map, None), &encode_message(&fields)).unwrap();
+        let ts = |name: &str, system_name: &str, line| {
+            (name.to_string(), system_name.to_string(), "assembly/index.ts".to_string(), line)
+        };
+        assert_eq!(location_functions(&rewritten), [
+            vec![ts("process", "wasm-function[2]:0x52", 6)],
+            vec![("main".to_string(), String::new(), String::new(), 9)],
+            vec![ts("calculate", "", 2)],
+            vec![],
+        ]);
+    }
+
+    #[test]
+    fn rewrites_cpuprofile_frames() {
+        let profile = serde_json::json!({
+            "nodes": [
+                {"id": 1, "callFrame": {"functionName": "(root)", "url": "", "lineNumber": -1, "columnNumber": -1}},
+                {"id": 2, "callFrame": {"functionName": "", "url": "wasm://wasm/abcd", "lineNumber": 0, "columnNumber": 0x52}},
+                {"id": 3, "callFrame": {"functionName": "wasm-function[1]:0x48", "url": "", "lineNumber": 0, "columnNumber": 0}},
+                {"id": 4, "callFrame": {"functionName": "main", "url": "app.js", "lineNumber": 3, "columnNumber": 7}},
+            ],
+        });
+        let map = map();
+        let rewritten = symbolicate_cpuprofile(&Symbolicator::new(&map, None), &profile.to_string()).unwrap();
+        let rewritten: Value = serde_json::from_str(&rewritten).unwrap();
+        let frames: Vec<&Value> = rewritten["nodes"].as_array().unwrap().iter().map(|n| &n["callFrame"]).collect();
+        assert_eq!(frames[0], &profile["nodes"][0]["callFrame"]);
+        assert_eq!(frames[1], &serde_json::json!(
+            {"functionName": "process", "url": "assembly/index.ts", "lineNumber": 5, "columnNumber": 2}
+        ));
+        assert_eq!(frames[2], &serde_json::json!(
+            {"functionName": "calculate", "url": "assembly/index.ts", "lineNumber": 1, "columnNumber": 2}
+        ));
+        assert_eq!(frames[3], &profile["nodes"][3]["callFrame"]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Offset queries in the forms runtimes report them, resolved to module-absolute offsets.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::parse_offset;
+use crate::wasm::WasmModule;
+
+/// A Wasm offset as given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetQuery {
+    /// A module-absolute offset, e.g. `0x3040`.
+    Module(u32),
+    /// An offset relative to the start of a function body, e.g. `func[17]:0x2a`.
+    Function { index: u32, offset: u32 },
+    /// A module-absolute offset in the body of a function, as V8 and
+    /// SpiderMonkey print frames, e.g. `wasm-function[17]:0x1c4`.
+    WasmFunction { index: u32, offset: u32 },
+    /// An offset relative to the start of the code section, e.g. `code:0x1c4`.
+    CodeSection(u32),
+}
+
+impl OffsetQuery {
+    /// Convert to a module-absolute offset.
+    ///
+    /// `wasm-function[N]:OFF` is only checked to fall in function N if the
+    /// module is known.
+    pub fn resolve(&self, module: Option<&WasmModule>) -> anyhow::Result<u32> {
+        match (*self, module) {
+            (OffsetQuery::Module(offset), _) | (OffsetQuery::WasmFunction { offset, .. }, None) => {
+                return Ok(offset);
+            }
+            _ => {}
+        }
+        let module = module.ok_or_else(|| anyhow::anyhow!(
+            "Offset '{}' is relative to the module, pass the .wasm file to resolve it", self
+        ))?;
+        let function = |index| module.function(index).ok_or_else(|| {
+            anyhow::anyhow!("No function with index {} defined in the module", index)
+        });
+        match *self {
+            OffsetQuery::Module(offset) => Ok(offset),
+            OffsetQuery::WasmFunction { index, offset } => {
+                let f = function(index)?;
+                match f.body.contains(&offset) {
+                    true => Ok(offset),
+                    false => Err(anyhow::anyhow!(
+                        "Offset 0x{:x} is outside function {} (body 0x{:x}..0x{:x})",
+                        offset, index, f.body.start, f.body.end
+                    )),
+                }
+            }
+            OffsetQuery::Function { index, offset } => {
+                let f = function(index)?;
+                let abs = f.body.start.checked_add(offset).filter(|o| *o < f.body.end);
+                abs.ok_or_else(|| anyhow::anyhow!(
+                    "Offset 0x{:x} is past the end of function {} (body size 0x{:x})",
+                    offset, index, f.body.end - f.body.start
+                ))
+            }
+            OffsetQuery::CodeSection(offset) => {
+                let code = module.code_section()
+                    .ok_or_else(|| anyhow::anyhow!("Module has no code section"))?;
+                code.start.checked_add(offset).filter(|o| *o < code.end).ok_or_else(|| {
+                    anyhow::anyhow!("Offset 0x{:x} is past the end of the code section", offset)
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for OffsetQuery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<OffsetQuery> {
+        let invalid = || anyhow::anyhow!("Invalid offset '{}'", s);
+        if let Some(rest) = s.strip_prefix("code:") {
+            return parse_offset(rest).map(OffsetQuery::CodeSection).ok_or_else(invalid);
+        }
+        let (rest, absolute) = match s.strip_prefix("wasm-function[") {
+            Some(rest) => (Some(rest), true),
+            None => (s.strip_prefix("func["), false),
+        };
+        if let Some(rest) = rest {
+            let (index, offset) = rest.split_once(']').ok_or_else(invalid)?;
+            let index = index.parse().map_err(|_| invalid())?;
+            // Without an offset, both forms stand for the start of the body.
+            let offset = match offset {
+                "" => return Ok(OffsetQuery::Function { index, offset: 0 }),
+                _ => offset.strip_prefix(':').and_then(parse_offset).ok_or_else(invalid)?,
+            };
+            return Ok(match absolute {
+                true => OffsetQuery::WasmFunction { index, offset },
+                false => OffsetQuery::Function { index, offset },
+            });
+        }
+        parse_offset(s).map(OffsetQuery::Module).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for OffsetQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OffsetQuery::Module(offset) => write!(f, "0x{:x}", offset),
+            OffsetQuery::Function { index, offset } => write!(f, "func[{}]:0x{:x}", index, offset),
+            OffsetQuery::WasmFunction { index, offset } => write!(f, "wasm-function[{}]:0x{:x}", index, offset),
+            OffsetQuery::CodeSection(offset) => write!(f, "code:0x{:x}", offset),
+        }
+    }
+}
+
+/// A span of Wasm offsets as given on the command line, `START..END` with
+/// `END` exclusive and both in any of the forms of [`OffsetQuery`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeQuery {
+    pub start: OffsetQuery,
+    pub end: OffsetQuery,
+}
+
+impl RangeQuery {
+    /// Convert to a module-absolute range.
+    pub fn resolve(&self, module: Option<&WasmModule>) -> anyhow::Result<Range<u32>> {
+        let start = self.start.resolve(module)?;
+        let end = self.end.resolve(module)?;
+        if end <= start {
+            anyhow::bail!("Empty range '{}'", self);
+        }
+        Ok(start..end)
+    }
+}
+
+impl FromStr for RangeQuery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<RangeQuery> {
+        let (start, end) = s.split_once("..")
+            .ok_or_else(|| anyhow::anyhow!("Invalid range '{}', expected START..END", s))?;
+        Ok(RangeQuery { start: start.parse()?, end: end.parse()? })
+    }
+}
+
+impl fmt::Display for RangeQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::testing::module;
+
+    #[test]
+    fn parse_forms() {
+        assert_eq!("0x3040".parse::<OffsetQuery>().unwrap(), OffsetQuery::Module(0x3040));
+        assert_eq!("code:16".parse::<OffsetQuery>().unwrap(), OffsetQuery::CodeSection(16));
+        assert_eq!("func[3]:0x2a".parse::<OffsetQuery>().unwrap(), OffsetQuery::Function { index: 3, offset: 0x2a });
+        assert_eq!(
+            "wasm-function[3]:0x1c4".parse::<OffsetQuery>().unwrap(),
+            OffsetQuery::WasmFunction { index: 3, offset: 0x1c4 },
+        );
+        assert_eq!("wasm-function[3]".parse::<OffsetQuery>().unwrap(), OffsetQuery::Function { index: 3, offset: 0 });
+        assert!("wasm-function[x]:0x1".parse::<OffsetQuery>().is_err());
+        assert!("func[1]0x1".parse::<OffsetQuery>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["0x3040", "code:0x10", "func[3]:0x2a", "wasm-function[3]:0x1c4"] {
+            assert_eq!(s.parse::<OffsetQuery>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn wasm_function_offsets_are_module_absolute() {
+        // two bodies of `nop nop end`, at 0x17..0x1b and 0x1c..0x20
+        let m = module(&[], &[&[0x01, 0x01, 0x0b], &[0x01, 0x01, 0x0b]]);
+        assert_eq!(m.function(1).unwrap().body, 0x1c..0x20);
+        let query = |s: &str| s.parse::<OffsetQuery>().unwrap().resolve(Some(&m));
+        assert_eq!(query("wasm-function[1]:0x1d").unwrap(), 0x1d);
+        assert_eq!(query("func[1]:0x1").unwrap(), 0x1d);
+        assert!(query("wasm-function[0]:0x1d").is_err());
+        assert!(query("wasm-function[1]:0x1").is_err());
+        assert!(query("wasm-function[2]:0x1d").is_err());
+        let unchecked = "wasm-function[7]:0x1d".parse::<OffsetQuery>().unwrap().resolve(None);
+        assert_eq!(unchecked.unwrap(), 0x1d);
+    }
+}
@@ -0,0 +1,124 @@
+//! Structured, serializable results of lookups for machine-readable output.
+
+use serde::Serialize;
+use std::ops::Range;
+
+use crate::diff::{MapDiff, OffsetTranslation};
+use crate::query::RangeQuery;
+use crate::reverse::{OffsetRange, SourcePosition};
+use crate::dwarf::InlinedCall;
+use crate::sourcemap::{MappingEntry, SourceMap};
+use crate::wasm::WasmModule;
+
+/// The function containing a queried offset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionReport {
+    pub index: u32,
+    pub name: Option<String>,
+    /// Offset of the query relative to the start of the function body.
+    pub offset: u32,
+}
+
+impl FunctionReport {
+    pub fn new(module: &WasmModule, offset: u32) -> Option<FunctionReport> {
+        module.function_at(offset).map(|f| FunctionReport {
+            index: f.index,
+            name: f.name.clone(),
+            offset: offset - f.body.start,
+        })
+    }
+}
+
+/// Result of a forward lookup of one offset.
+///
+/// All fields other than `query_offset` are `null` when no mapping precedes
+/// the offset; `source`, `line`, `column` and `name` are `null` for runtime
+/// generated segments, in which case `closest` holds the preceding TS source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LookupReport {
+    pub query_offset: u32,
+    pub matched_offset: Option<u32>,
+    pub exact: bool,
+    pub source: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub name: Option<String>,
+    pub closest: Option<MappingEntry>,
+    /// Only reported when the module is available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionReport>,
+    /// Calls inlined at the offset, innermost first; only from DWARF.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inlined: Vec<InlinedCall>,
+}
+
+impl LookupReport {
+    pub fn new(map: &SourceMap, query_offset: u32, module: Option<&WasmModule>) -> LookupReport {
+        let function = module.and_then(|m| FunctionReport::new(m, query_offset));
+        let inlined = map.inlined_calls(query_offset);
+        match map.lookup(query_offset) {
+            Some(loc) => LookupReport {
+                query_offset,
+                matched_offset: Some(loc.entry.gen_offset),
+                exact: loc.is_exact(),
+                source: loc.entry.source,
+                line: loc.entry.line,
+                column: loc.entry.column,
+                name: loc.entry.name,
+                closest: loc.closest,
+                function,
+                inlined,
+            },
+            None => LookupReport {
+                query_offset,
+                matched_offset: None,
+                exact: false,
+                source: None,
+                line: None,
+                column: None,
+                name: None,
+                closest: None,
+                function,
+                inlined,
+            },
+        }
+    }
+}
+
+/// Result of a reverse lookup of one source position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReverseReport {
+    pub query: String,
+    pub ranges: Vec<OffsetRange>,
+}
+
+impl ReverseReport {
+    pub fn new(pos: &SourcePosition, ranges: Vec<OffsetRange>) -> ReverseReport {
+        ReverseReport { query: pos.to_string(), ranges }
+    }
+}
+
+/// Result of listing the mappings of an offset range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RangeReport {
+    pub query: String,
+    pub start: u32,
+    /// Exclusive.
+    pub end: u32,
+    /// Runs of consecutive entries from the same source line.
+    pub runs: Vec<OffsetRange>,
+}
+
+impl RangeReport {
+    pub fn new(query: &RangeQuery, range: Range<u32>, map: &SourceMap) -> RangeReport {
+        RangeReport { query: query.to_string(), start: range.start, end: range.end, runs: map.line_runs(range) }
+    }
+}
+
+/// Result of comparing two maps, with offsets of the old build translated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffReport {
+    #[serde(flatten)]
+    pub diff: MapDiff,
+    pub offsets: Vec<OffsetTranslation>,
+}
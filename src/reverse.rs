@@ -0,0 +1,211 @@
+//! Reverse lookup from a TS source position to the Wasm offsets generated from it.
+
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sourcemap::{MappingEntry, SourceMap};
+
+/// A `file:line[:column]` position in the original sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub source: String,
+    /// 1-based line.
+    pub line: u32,
+    /// 0-based column; `None` matches the whole line.
+    pub column: Option<u32>,
+}
+
+impl FromStr for SourcePosition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<SourcePosition> {
+        let invalid = || anyhow::anyhow!("Invalid source position '{}', expected file:line[:column]", s);
+        let (rest, last) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let last: u32 = last.parse().map_err(|_| invalid())?;
+        // `file:line:col` if the part before the last colon also ends in a number
+        if let Some((source, line)) = rest.rsplit_once(':')
+            && let Ok(line) = line.parse::<u32>()
+            && !source.is_empty()
+        {
+            return Ok(SourcePosition { source: source.to_string(), line, column: Some(last) });
+        }
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(SourcePosition { source: rest.to_string(), line: last, column: None })
+    }
+}
+
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source, self.line)?;
+        if let Some(col) = self.column {
+            write!(f, ":{}", col)?;
+        }
+        Ok(())
+    }
+}
+
+/// A run of generated code attributed to one source position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OffsetRange {
+    pub start: u32,
+    /// Exclusive end, i.e. the next entry's offset; `None` for the last entry.
+    pub end: Option<u32>,
+    /// The first entry of the run.
+    pub entry: MappingEntry,
+}
+
+/// Entries of a map by source and line, the part of a [`ReverseIndex`] that
+/// can be built once and kept alongside its map.
+#[derive(Debug, Clone, Default)]
+pub struct LineTable {
+    by_line: HashMap<String, HashMap<u32, Vec<usize>>>,
+}
+
+impl LineTable {
+    pub fn new(map: &SourceMap) -> LineTable {
+        let mut by_line: HashMap<String, HashMap<u32, Vec<usize>>> = HashMap::new();
+        for (i, e) in map.entries().iter().enumerate() {
+            if let (Some(source), Some(line)) = (e.source.as_deref(), e.line) {
+                by_line.entry(source.to_string()).or_default().entry(line).or_default().push(i);
+            }
+        }
+        LineTable { by_line }
+    }
+
+    fn get(&self, source: &str, line: u32) -> Option<&Vec<usize>> {
+        self.by_line.get(source)?.get(&line)
+    }
+}
+
+/// Index from `(source, line)` to the entries generated from that line.
+pub struct ReverseIndex<'a> {
+    map: &'a SourceMap,
+    table: Cow<'a, LineTable>,
+}
+
+impl<'a> ReverseIndex<'a> {
+    pub fn new(map: &'a SourceMap) -> ReverseIndex<'a> {
+        ReverseIndex { map, table: Cow::Owned(LineTable::new(map)) }
+    }
+
+    /// An index of `map` reusing `table`, which must have been built from `map`.
+    pub fn with_table(map: &'a SourceMap, table: &'a LineTable) -> ReverseIndex<'a> {
+        ReverseIndex { map, table: Cow::Borrowed(table) }
+    }
+
+    /// Sources in the map matching `source` exactly, or else by path suffix.
+    pub fn resolve_source(&self, source: &str) -> Vec<&'a str> {
+        let sources = &self.map.sources;
+        if let Some(s) = sources.iter().find(|s| *s == source) {
+            return vec![s.as_str()];
+        }
+        let suffix = format!("/{}", source.trim_start_matches("./"));
+        sources.iter()
+            .filter(|s| s.ends_with(&suffix) || s.trim_start_matches("./") == &suffix[1..])
+            .map(|s| s.as_str())
+            .collect()
+    }
+
+    /// All offset ranges generated from `pos`, in ascending offset order.
+    ///
+    /// Adjacent entries with the same line and column are merged into one range.
+    pub fn lookup(&self, pos: &SourcePosition) -> Vec<OffsetRange> {
+        let entries = self.map.entries();
+        let mut indices: Vec<usize> = self.resolve_source(&pos.source).into_iter()
+            .filter_map(|s| self.table.get(s, pos.line))
+            .flatten()
+            .copied()
+            .filter(|&i| pos.column.is_none() || entries[i].column == pos.column)
+            .collect();
+        indices.sort_unstable();
+
+        let mut ranges: Vec<OffsetRange> = Vec::new();
+        let mut prev_idx = None;
+        for i in indices {
+            let e = &entries[i];
+            let end = entries[i + 1..].iter()
+                .map(|n| n.gen_offset)
+                .find(|&o| o > e.gen_offset);
+            if let (Some(prev), Some(last)) = (prev_idx, ranges.last_mut())
+                && prev + 1 == i
+                && last.entry.source == e.source
+                && last.entry.column == e.column
+            {
+                last.end = end;
+                prev_idx = Some(i);
+                continue;
+            }
+            ranges.push(OffsetRange { start: e.gen_offset, end, entry: e.clone() });
+            prev_idx = Some(i);
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(source: &str, line: u32, column: Option<u32>) -> SourcePosition {
+        SourcePosition { source: source.to_string(), line, column }
+    }
+
+    fn entry(gen_offset: u32, source: &str, line: u32, column: u32) -> MappingEntry {
+        MappingEntry { gen_offset, source: Some(source.to_string()), line: Some(line), column: Some(column), name: None }
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!("a.ts:3".parse::<SourcePosition>().unwrap(), pos("a.ts", 3, None));
+        assert_eq!("a.ts:3:4".parse::<SourcePosition>().unwrap(), pos("a.ts", 3, Some(4)));
+        assert_eq!("dir:12/a.ts:3".parse::<SourcePosition>().unwrap(), pos("dir:12/a.ts", 3, None));
+        assert_eq!("12:3".parse::<SourcePosition>().unwrap(), pos("12", 3, None));
+        // a number between the last two colons is read as the line
+        assert_eq!("dir:12:3".parse::<SourcePosition>().unwrap(), pos("dir", 12, Some(3)));
+        for s in ["a.ts", "a.ts:x", ":3", "a.ts:3:"] {
+            assert!(s.parse::<SourcePosition>().is_err(), "{}", s);
+        }
+        assert_eq!(pos("a.ts", 3, Some(4)).to_string(), "a.ts:3:4");
+    }
+
+    #[test]
+    fn resolves_sources_by_path_suffix() {
+        let map = SourceMap::from_entries(vec![
+            entry(0, "assembly/index.ts", 1, 0),
+            entry(1, "./lib/util.ts", 1, 0),
+            entry(2, "index.ts", 1, 0),
+        ]);
+        let index = ReverseIndex::new(&map);
+        assert_eq!(index.resolve_source("index.ts"), ["index.ts"]);
+        assert_eq!(index.resolve_source("./index.ts"), ["assembly/index.ts", "index.ts"]);
+        assert_eq!(index.resolve_source("util.ts"), ["./lib/util.ts"]);
+        assert_eq!(index.resolve_source("lib/util.ts"), ["./lib/util.ts"]);
+        assert_eq!(index.resolve_source("./lib/util.ts"), ["./lib/util.ts"]);
+        assert!(index.resolve_source("til.ts").is_empty());
+    }
+
+    #[test]
+    fn merges_adjacent_entries_of_a_position() {
+        let map = SourceMap::from_entries(vec![
+            entry(10, "a.ts", 1, 0),
+            entry(12, "a.ts", 1, 0),
+            entry(14, "a.ts", 2, 0),
+            entry(16, "a.ts", 1, 0),
+            entry(20, "a.ts", 1, 4),
+        ]);
+        let index = ReverseIndex::new(&map);
+        let ranges = |pos: SourcePosition| -> Vec<(u32, Option<u32>)> {
+            index.lookup(&pos).into_iter().map(|r| (r.start, r.end)).collect()
+        };
+        assert_eq!(ranges(pos("a.ts", 1, None)), [(10, Some(14)), (16, Some(20)), (20, None)]);
+        assert_eq!(ranges(pos("a.ts", 1, Some(0))), [(10, Some(14)), (16, Some(20))]);
+        assert_eq!(ranges(pos("a.ts", 1, Some(4))), [(20, None)]);
+        assert_eq!(ranges(pos("a.ts", 2, None)), [(14, Some(16))]);
+        assert!(ranges(pos("a.ts", 3, None)).is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+//! A long-running lookup service speaking JSON-RPC 2.0, over newline-delimited
+//! stdin/stdout or a localhost HTTP endpoint.
+//!
+//! Methods (`map` may be omitted while exactly one map is loaded):
+//!
+//! - `lookup`: `{"map": KEY, "offset": 77 | "0x4d" | "func[1]:0x7"}` → a [`LookupReport`]
+//! - `batch_lookup`: `{"map": KEY, "offsets": [...]}` → an array of [`LookupReport`]
+//! - `reverse_lookup`: `{"map": KEY, "position": "src/main.ts:42[:15]"}` → a [`ReverseReport`]
+//! - `load`: `{"path": PATH, "key": KEY}` → `{"key": KEY, "entries": N}`, `key` defaults to `path`;
+//!   not available over HTTP
+//! - `maps`: → `[{"key": KEY, "entries": N}, ...]`
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::net::ToSocketAddrs;
+use std::path::Path;
+
+use crate::paths::SourcePaths;
+use crate::query::OffsetQuery;
+use crate::report::{LookupReport, ReverseReport};
+use crate::reverse::{LineTable, ReverseIndex, SourcePosition};
+use crate::sourcemap::SourceMap;
+use crate::wasm::WasmModule;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError { code, message: message.into() }
+    }
+
+    fn invalid_params(e: impl std::fmt::Display) -> RpcError {
+        RpcError::new(INVALID_PARAMS, format!("{:#}", e))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupParams {
+    map: Option<String>,
+    offset: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchLookupParams {
+    map: Option<String>,
+    offsets: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseLookupParams {
+    map: Option<String>,
+    position: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoadParams {
+    path: String,
+    key: Option<String>,
+}
+
+/// A source map kept in memory together with its module, if known, and the
+/// table for reverse lookups.
+pub struct LoadedMap {
+    pub map: SourceMap,
+    pub module: Option<WasmModule>,
+    lines: LineTable,
+}
+
+impl LoadedMap {
+    pub fn new(map: SourceMap, module: Option<WasmModule>) -> LoadedMap {
+        let lines = LineTable::new(&map);
+        LoadedMap { map, module, lines }
+    }
+
+    pub fn reverse_index(&self) -> ReverseIndex<'_> {
+        ReverseIndex::with_table(&self.map, &self.lines)
+    }
+}
+
+/// Answers lookup requests against a set of preloaded maps.
+#[derive(Default)]
+pub struct LookupService {
+    maps: BTreeMap<String, LoadedMap>,
+    source_paths: SourcePaths,
+    /// Whether the `load` method is refused, as it opens any path a client names.
+    load_disabled: bool,
+}
+
+impl LookupService {
+    pub fn new() -> LookupService {
+        LookupService::default()
+    }
+
+    /// Rewrite the source paths of every map loaded from now on.
+    pub fn with_source_paths(mut self, source_paths: SourcePaths) -> LookupService {
+        self.source_paths = source_paths;
+        self
+    }
+
+    /// Load a map (or a `.wasm` module and its map) under `key`, replacing
+    /// any map previously loaded under it.
+    pub fn load(&mut self, key: &str, path: impl AsRef<Path>) -> Result<&LoadedMap> {
+        let (mut map, module) = SourceMap::load_with_module(path)?;
+        self.source_paths.apply(&mut map);
+        self.insert(key, LoadedMap::new(map, module));
+        Ok(&self.maps[key])
+    }
+
+    /// Register an already parsed map under `key`.
+    pub fn insert(&mut self, key: &str, map: LoadedMap) {
+        self.maps.insert(key.to_string(), map);
+    }
+
+    /// Handle one request line: a JSON-RPC request or batch.
+    ///
+    /// Returns `None` when no response is due, i.e. for notifications.
+    pub fn handle_str(&mut self, request: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(request) {
+            Ok(request) => self.handle(request)?,
+            Err(e) => error_response(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())),
+        };
+        Some(response.to_string())
+    }
+
+    /// Handle a parsed JSON-RPC request or batch.
+    pub fn handle(&mut self, request: Value) -> Option<Value> {
+        match request {
+            Value::Array(batch) if !batch.is_empty() => {
+                let responses: Vec<Value> = batch.into_iter().filter_map(|r| self.handle_one(r)).collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            request => self.handle_one(request),
+        }
+    }
+
+    fn handle_one(&mut self, request: Value) -> Option<Value> {
+        let request: RpcRequest = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string()))),
+        };
+        let result = self.dispatch(&request.method, request.params);
+        // requests without an id are notifications
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => error_response(id, e),
+        })
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "lookup" => {
+                let p: LookupParams = parse_params(params)?;
+                let loaded = self.get(p.map.as_deref())?;
+                to_value(lookup(loaded, &p.offset)?)
+            }
+            "batch_lookup" => {
+                let p: BatchLookupParams = parse_params(params)?;
+                let loaded = self.get(p.map.as_deref())?;
+                let reports: Result<Vec<LookupReport>, RpcError> =
+                    p.offsets.iter().map(|offset| lookup(loaded, offset)).collect();
+                to_value(reports?)
+            }
+            "reverse_lookup" => {
+                let p: ReverseLookupParams = parse_params(params)?;
+                let loaded = self.get(p.map.as_deref())?;
+                let pos: SourcePosition = p.position.parse().map_err(RpcError::invalid_params)?;
+                let ranges = loaded.reverse_index().lookup(&pos);
+                to_value(ReverseReport::new(&pos, ranges))
+            }
+            "load" if self.load_disabled => {
+                Err(RpcError::new(METHOD_NOT_FOUND, "Method 'load' is not available over HTTP"))
+            }
+            "load" => {
+                let p: LoadParams = parse_params(params)?;
+                let key = p.key.unwrap_or_else(|| p.path.clone());
+                let loaded = self.load(&key, &p.path)
+                    .map_err(|e| RpcError::new(SERVER_ERROR, format!("{:#}", e)))?;
+                Ok(json!({"key": key, "entries": loaded.map.entries().len()}))
+            }
+            "maps" => {
+                let maps: Vec<Value> = self.maps.iter()
+                    .map(|(key, loaded)| json!({"key": key, "entries": loaded.map.entries().len()}))
+                    .collect();
+                Ok(Value::Array(maps))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+
+    fn get(&self, key: Option<&str>) -> Result<&LoadedMap, RpcError> {
+        match key {
+            Some(key) => self.maps.get(key)
+                .ok_or_else(|| RpcError::invalid_params(format!("No map loaded under key '{}'", key))),
+            None if self.maps.len() == 1 => Ok(self.maps.values().next().unwrap()),
+            None => Err(RpcError::invalid_params("Missing 'map' key: more than one map (or none) is loaded")),
+        }
+    }
+
+    /// Serve newline-delimited JSON-RPC until `input` is exhausted.
+    pub fn serve_stdio<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_str(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail unless `addr` only resolves to loopback addresses, as required by
+    /// [`LookupService::serve_http`].
+    pub fn check_http_addr(addr: &str) -> Result<()> {
+        let addrs: Vec<_> = addr.to_socket_addrs()
+            .with_context(|| format!("Invalid address '{}'", addr))?
+            .collect();
+        if addrs.is_empty() || addrs.iter().any(|a| !a.ip().is_loopback()) {
+            anyhow::bail!("Refusing to listen on '{}': only loopback addresses are allowed", addr);
+        }
+        Ok(())
+    }
+
+    /// Serve JSON-RPC over HTTP POST requests on `addr`, e.g. `127.0.0.1:8080`.
+    ///
+    /// There is no authentication, so only loopback addresses are accepted,
+    /// and `load` is disabled since any local process or web page could
+    /// otherwise make the server open files.
+    pub fn serve_http(&mut self, addr: &str) -> Result<()> {
+        LookupService::check_http_addr(addr)?;
+        self.load_disabled = true;
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to listen on '{}'", addr))?;
+        let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+        for mut request in server.incoming_requests() {
+            if *request.method() != tiny_http::Method::Post {
+                let response = tiny_http::Response::from_string("POST a JSON-RPC 2.0 request\n")
+                    .with_status_code(405);
+                let _ = request.respond(response);
+                continue;
+            }
+            let mut body = String::new();
+            let response = match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => match self.handle_str(&body) {
+                    Some(json) => tiny_http::Response::from_string(json).with_header(content_type.clone()),
+                    None => tiny_http::Response::from_string("").with_status_code(204),
+                },
+                Err(e) => tiny_http::Response::from_string(e.to_string()).with_status_code(400),
+            };
+            // a client hanging up early is not our problem
+            let _ = request.respond(response);
+        }
+        Ok(())
+    }
+}
+
+fn lookup(loaded: &LoadedMap, offset: &Value) -> Result<LookupReport, RpcError> {
+    let query = match offset {
+        Value::Number(n) => n.as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .map(OffsetQuery::Module)
+            .ok_or_else(|| RpcError::invalid_params(format!("Invalid offset {}", n)))?,
+        Value::String(s) => s.parse().map_err(RpcError::invalid_params)?,
+        _ => return Err(RpcError::invalid_params("Offsets must be numbers or strings")),
+    };
+    let offset = query.resolve(loaded.module.as_ref()).map_err(RpcError::invalid_params)?;
+    Ok(LookupReport::new(&loaded.map, offset, loaded.module.as_ref()))
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn to_value<T: serde::Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": e.code, "message": e.message}})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sourcemap::MappingEntry;
+
+    fn service(keys: &[&str]) -> LookupService {
+        let mut service = LookupService::new();
+        for key in keys {
+            let entry = MappingEntry {
+                gen_offset: 0x40,
+                source: Some("a.ts".to_string()),
+                line: Some(3),
+                column: Some(4),
+                name: None,
+            };
+            service.insert(key, LoadedMap::new(SourceMap::from_entries(vec![entry]), None));
+        }
+        service
+    }
+
+    fn call(service: &mut LookupService, request: &str) -> Value {
+        serde_json::from_str(&service.handle_str(request).expect("a response")).unwrap()
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn lookup() {
+        let response = call(&mut service(&["a"]), r#"{"jsonrpc":"2.0","id":7,"method":"lookup","params":{"offset":"0x42"}}"#);
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["source"], "a.ts");
+        assert_eq!(response["result"]["line"], 3);
+        assert_eq!(response["result"]["matched_offset"], 0x40);
+    }
+
+    #[test]
+    fn reverse_lookup() {
+        let mut service = service(&["a"]);
+        let response = call(&mut service, r#"{"jsonrpc":"2.0","id":1,"method":"reverse_lookup","params":{"map":"a","position":"a.ts:3"}}"#);
+        assert_eq!(response["result"]["ranges"][0]["start"], 0x40);
+        let response = call(&mut service, r#"{"jsonrpc":"2.0","id":2,"method":"reverse_lookup","params":{"map":"a","position":"a.ts:4"}}"#);
+        assert_eq!(response["result"]["ranges"], json!([]));
+    }
+
+    #[test]
+    fn error_codes() {
+        let mut service = service(&["a", "b"]);
+        let response = call(&mut service, "{\"jsonrpc\":");
+        assert_eq!((error_code(&response), &response["id"]), (PARSE_ERROR, &Value::Null));
+        assert_eq!(error_code(&call(&mut service, r#"{"jsonrpc":"2.0","id":1}"#)), INVALID_REQUEST);
+        assert_eq!(error_code(&call(&mut service, "[]")), INVALID_REQUEST);
+        assert_eq!(error_code(&call(&mut service, r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#)), METHOD_NOT_FOUND);
+        let invalid = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"lookup","params":{"map":"a","offset":true}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"method":"lookup","params":{"map":"c","offset":1}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"method":"lookup","params":{"offset":1}}"#,
+            r#"{"jsonrpc":"2.0","id":1,"method":"reverse_lookup","params":{"map":"a"}}"#,
+        ];
+        for request in invalid {
+            assert_eq!(error_code(&call(&mut service, request)), INVALID_PARAMS, "{}", request);
+        }
+        let response = call(&mut service, r#"{"jsonrpc":"2.0","id":1,"method":"load","params":{"path":"/nonexistent.wasm.map"}}"#);
+        assert_eq!(error_code(&response), SERVER_ERROR);
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        let mut service = service(&["a"]);
+        assert_eq!(service.handle_str(r#"{"jsonrpc":"2.0","method":"lookup","params":{"offset":66}}"#), None);
+        assert_eq!(service.handle_str(r#"{"jsonrpc":"2.0","method":"nope"}"#), None);
+        assert_eq!(service.handle_str(r#"[{"jsonrpc":"2.0","method":"maps"}]"#), None);
+    }
+
+    #[test]
+    fn batches() {
+        let response = call(&mut service(&["a"]), r#"[
+            {"jsonrpc":"2.0","id":1,"method":"maps"},
+            {"jsonrpc":"2.0","method":"maps"},
+            {"jsonrpc":"2.0","id":2,"method":"nope"},
+            42
+        ]"#);
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["result"], json!([{"key": "a", "entries": 1}]));
+        assert_eq!((&responses[1]["id"], error_code(&responses[1])), (&json!(2), METHOD_NOT_FOUND));
+        assert_eq!(error_code(&responses[2]), INVALID_REQUEST);
+    }
+
+    #[test]
+    fn http_refuses_load_and_remote_binds() {
+        let mut service = service(&["a"]);
+        assert!(LookupService::check_http_addr("127.0.0.1:0").is_ok());
+        assert!(LookupService::check_http_addr("[::1]:0").is_ok());
+        assert!(LookupService::check_http_addr("0.0.0.0:0").is_err());
+        assert!(service.serve_http("192.0.2.1:0").is_err());
+        service.load_disabled = true;
+        let response = call(&mut service, r#"{"jsonrpc":"2.0","id":1,"method":"load","params":{"path":"/etc/passwd"}}"#);
+        assert_eq!(error_code(&response), METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,31 @@
+//! Compiler-style rendering of a source line with surrounding context.
+
+/// Render `line` (1-based) of `text` with `context` lines before and after it,
+/// and a caret under `column` (0-based) if given.
+///
+/// Returns `None` if `line` is outside of `text`.
+pub fn render_snippet(text: &str, line: u32, column: Option<u32>, context: u32) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let target = line.checked_sub(1)? as usize;
+    if target >= lines.len() {
+        return None;
+    }
+    let first = target.saturating_sub(context as usize);
+    let last = (target + context as usize).min(lines.len() - 1);
+    let width = (last + 1).to_string().len();
+
+    let mut out = String::new();
+    for (i, src) in lines.iter().enumerate().take(last + 1).skip(first) {
+        let marker = if i == target { '>' } else { ' ' };
+        out.push_str(&format!("{} {:>width$} | {}\n", marker, i + 1, src));
+        if i == target && let Some(col) = column {
+            // keep tabs so the caret lines up with the rendered source
+            let pad: String = src.chars()
+                .take(col as usize)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&format!("  {:>width$} | {}^\n", "", pad));
+        }
+    }
+    Some(out)
+}
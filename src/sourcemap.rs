@@ -0,0 +1,1088 @@
+//! Parsing of AssemblyScript `.wasm.map` files into a sorted entry table.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use crate::dwarf::{has_dwarf, read_dwarf, InlinedCall, InlinedRange};
+use crate::reverse::OffsetRange;
+use crate::vlq::{vlq_decode, vlq_encode};
+use crate::wasm::{is_wasm, WasmModule};
+
+/// Index maps may reference further maps; bound the nesting to stop cycles.
+pub(crate) const MAX_SECTION_DEPTH: usize = 8;
+
+/// The JSON layout of a v3 source map as emitted by the AssemblyScript compiler,
+/// or of an index map with `sections` instead of `mappings`.
+#[derive(Debug, Deserialize, Serialize)]
+struct RawSourceMap {
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(rename = "sourceRoot", skip_serializing_if = "Option::is_none")]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default, rename = "sourcesContent", skip_serializing_if = "Vec::is_empty")]
+    sources_content: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mappings: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sections: Option<Vec<RawSection>>,
+}
+
+/// One entry of an index map's `sections`, embedding its map or referencing it by `url`.
+#[derive(Debug, Deserialize, Serialize)]
+struct RawSection {
+    offset: RawSectionOffset,
+    map: Option<Box<RawSourceMap>>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct RawSectionOffset {
+    line: u32,
+    column: u32,
+}
+
+/// One decoded `mappings` segment.
+///
+/// For Wasm source maps the generated column is the byte offset into the module.
+/// Segments with a single field carry no source information.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MappingEntry {
+    pub gen_offset: u32,
+    pub source: Option<String>,
+    /// 1-based line in `source`.
+    pub line: Option<u32>,
+    /// 0-based column in `source`.
+    pub column: Option<u32>,
+    /// Entry of `names` referenced by the optional 5th field, if in range.
+    pub name: Option<String>,
+}
+
+impl MappingEntry {
+    /// `file:line:column`, with placeholders for what the entry lacks.
+    pub fn position(&self) -> String {
+        format!("{}:{}:{}",
+            self.source.as_deref().unwrap_or("(no source)"),
+            self.line.map(|n| n.to_string()).unwrap_or("?".to_string()),
+            self.column.map(|n| n.to_string()).unwrap_or("?".to_string()),
+        )
+    }
+}
+
+/// Result of looking up a single Wasm offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The offset that was queried.
+    pub query_offset: u32,
+    /// The entry with the greatest `gen_offset <= query_offset`.
+    pub entry: MappingEntry,
+    /// When `entry` has no source (runtime generated code), the closest
+    /// preceding entry that does.
+    pub closest: Option<MappingEntry>,
+}
+
+impl Location {
+    /// Whether the matched entry starts exactly at the queried offset.
+    pub fn is_exact(&self) -> bool {
+        self.entry.gen_offset == self.query_offset
+    }
+
+    /// Whether the matched segment has no source, i.e. is runtime generated.
+    pub fn is_unmapped(&self) -> bool {
+        self.entry.source.is_none()
+    }
+}
+
+/// A parsed source map with its mappings decoded and sorted by offset.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    pub version: u32,
+    /// The generated file the map belongs to, e.g. `program.wasm`.
+    pub file: Option<String>,
+    /// Prefix of every entry of `sources` when resolving them.
+    pub source_root: Option<String>,
+    /// Source names as printed, i.e. as in the map unless rewritten with
+    /// [`SourceMap::map_sources`].
+    pub sources: Vec<String>,
+    pub names: Vec<String>,
+    /// Embedded source texts, parallel to `sources`; may be shorter or empty.
+    pub sources_content: Vec<Option<String>>,
+    entries: Vec<MappingEntry>,
+    /// `sources` as in the map, for reading source files from disk.
+    original_sources: Vec<String>,
+    /// Location of the map file, if it was read from disk.
+    path: Option<PathBuf>,
+    /// Inlined calls, only known from DWARF debug info.
+    inlined: Vec<InlinedRange>,
+}
+
+impl SourceMap {
+    /// Parse a source map from its JSON text.
+    ///
+    /// Index maps are flattened into one entry table; sections referenced by
+    /// `url` are read relative to the current directory.
+    pub fn parse(data: &str) -> Result<SourceMap> {
+        SourceMap::parse_at(data, None)
+    }
+
+    /// Parse a source map whose file (real or notional) is at `path`.
+    fn parse_at(data: &str, path: Option<&Path>) -> Result<SourceMap> {
+        let raw: RawSourceMap =
+            serde_json::from_str(data).context("Failed to parse source map JSON")?;
+        let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+        let mut sm = SourceMap::from_raw(raw, dir, 0)?;
+
+        if sm.entries.is_empty() {
+            anyhow::bail!("No mapping entries parsed from 'mappings' field. The map might not include VLQ mappings.");
+        }
+
+        sm.path = path.map(Path::to_path_buf);
+        sm.original_sources = sm.sources.clone();
+        Ok(sm)
+    }
+
+    fn from_raw(raw: RawSourceMap, dir: &Path, depth: usize) -> Result<SourceMap> {
+        let mut sm = SourceMap {
+            version: raw.version,
+            file: raw.file,
+            source_root: raw.source_root.filter(|root| !root.is_empty()),
+            sources: Vec::new(),
+            names: Vec::new(),
+            sources_content: Vec::new(),
+            entries: Vec::new(),
+            original_sources: Vec::new(),
+            path: None,
+            inlined: Vec::new(),
+        };
+        let sections = match (raw.mappings, raw.sections) {
+            (Some(mappings), _) => {
+                sm.entries = decode_mappings(&mappings, &raw.sources, &raw.names)?;
+                sm.sources = raw.sources;
+                sm.names = raw.names;
+                sm.sources_content = raw.sources_content;
+                return Ok(sm);
+            }
+            (None, Some(sections)) => sections,
+            (None, None) => anyhow::bail!("Source map has neither 'mappings' nor 'sections'"),
+        };
+        if depth >= MAX_SECTION_DEPTH {
+            anyhow::bail!("Index map sections nested deeper than {} levels", MAX_SECTION_DEPTH);
+        }
+
+        for (i, section) in sections.iter().enumerate() {
+            // a Wasm module is a single generated line; columns are byte offsets
+            if section.offset.line != 0 {
+                anyhow::bail!("Section {} starts at generated line {}, but Wasm source maps have a single line", i, section.offset.line);
+            }
+            if i > 0 && section.offset.column <= sections[i - 1].offset.column {
+                anyhow::bail!("Section {} at offset {} does not start after section {} at offset {}",
+                    i, section.offset.column, i - 1, sections[i - 1].offset.column);
+            }
+        }
+        // each section ends where the next one starts
+        let ends: Vec<Option<u32>> = sections.iter().skip(1).map(|s| Some(s.offset.column)).chain([None]).collect();
+
+        for (i, (section, end)) in sections.into_iter().zip(ends).enumerate() {
+            // sources of a section are relative to its own map and `sourceRoot`
+            let (sub, rel_dir) = match (section.map, section.url) {
+                (Some(map), _) => SourceMap::from_raw(*map, dir, depth + 1).map(|sub| (sub, String::new())),
+                (None, Some(url)) => {
+                    let url = url.strip_prefix("file://").unwrap_or(&url).to_string();
+                    let path = dir.join(&url);
+                    let rel_dir = url.rsplit_once('/').map_or("", |(d, _)| d).to_string();
+                    fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read section map '{}'", path.display()))
+                        .and_then(|data| Ok(serde_json::from_str::<RawSourceMap>(&data)?))
+                        .and_then(|raw| {
+                            let sub_dir = path.parent().unwrap_or(Path::new(""));
+                            SourceMap::from_raw(raw, sub_dir, depth + 1)
+                        })
+                        .map(|sub| (sub, rel_dir))
+                }
+                (None, None) => Err(anyhow::anyhow!("Section has neither 'map' nor 'url'")),
+            }.with_context(|| format!("Failed to load section {}", i))?;
+            let mut sub = sub;
+            let prefix = join_source(&rel_dir, sub.source_root.as_deref().unwrap_or(""));
+            sub.map_sources(|source| join_source(&prefix, source));
+            sm.append_section(sub, section.offset.column, end)?;
+        }
+
+        // ascendant
+        sm.entries.sort_by_key(|e| e.gen_offset);
+        Ok(sm)
+    }
+
+    /// Merge the sources, names and entries of an index map section starting at
+    /// `offset`, dropping entries at or after `end`, where the next section starts.
+    fn append_section(&mut self, section: SourceMap, offset: u32, end: Option<u32>) -> Result<()> {
+        for (i, source) in section.sources.into_iter().enumerate() {
+            let content = section.sources_content.get(i).cloned().flatten();
+            let index = match self.sources.iter().position(|s| *s == source) {
+                // a source listed by an earlier section may only now come with its text
+                Some(index) if content.is_some() && self.sources_content.get(index).is_none_or(Option::is_none) => index,
+                Some(_) => continue,
+                None => {
+                    self.sources.push(source);
+                    self.sources.len() - 1
+                }
+            };
+            if content.is_some() {
+                self.sources_content.resize(self.sources.len(), None);
+                self.sources_content[index] = content;
+            }
+        }
+        let known: HashSet<String> = self.names.iter().cloned().collect();
+        self.names.extend(section.names.into_iter().filter(|name| !known.contains(name)));
+        for mut e in section.entries {
+            e.gen_offset = e.gen_offset.checked_add(offset)
+                .ok_or_else(|| anyhow::anyhow!("Section entry offset overflows u32"))?;
+            if end.is_some_and(|end| e.gen_offset >= end) {
+                continue;
+            }
+            self.entries.push(e);
+        }
+        Ok(())
+    }
+
+    /// Build a map from decoded entries, e.g. to write a filtered or merged
+    /// map with [`SourceMap::to_json`].
+    ///
+    /// `sources` and `names` are collected from the entries in order of first use.
+    pub fn from_entries(mut entries: Vec<MappingEntry>) -> SourceMap {
+        entries.sort_by_key(|e| e.gen_offset);
+        let mut sources: Vec<String> = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        for e in &entries {
+            if let Some(source) = &e.source
+                && seen.insert(source)
+            {
+                sources.push(source.clone());
+            }
+        }
+        seen.clear();
+        for e in &entries {
+            if let Some(name) = &e.name
+                && seen.insert(name)
+            {
+                names.push(name.clone());
+            }
+        }
+        SourceMap {
+            version: 3,
+            file: None,
+            source_root: None,
+            original_sources: sources.clone(),
+            sources,
+            names,
+            sources_content: Vec::new(),
+            entries,
+            path: None,
+            inlined: Vec::new(),
+        }
+    }
+
+    /// Chain this map, from a module to an intermediate one it was built
+    /// from, with `inner`, from that intermediate module to the original
+    /// sources. E.g. compose the map written by `wasm-opt` with the one of `asc`.
+    ///
+    /// The original columns of this map are byte offsets into the intermediate
+    /// module. Each entry is resolved through `inner` like a lookup; entries
+    /// that resolve to nothing or to runtime generated code become unmapped.
+    pub fn compose(&self, inner: &SourceMap) -> SourceMap {
+        let entries = self.entries.iter().map(|e| {
+            let resolved = e.source.as_ref().and(e.column)
+                .and_then(|offset| inner.lookup(offset))
+                .map(|loc| loc.entry)
+                .filter(|entry| entry.source.is_some());
+            match resolved {
+                Some(entry) => MappingEntry {
+                    gen_offset: e.gen_offset,
+                    name: entry.name.or_else(|| e.name.clone()),
+                    ..entry
+                },
+                None => MappingEntry { gen_offset: e.gen_offset, source: None, line: None, column: None, name: None },
+            }
+        }).collect();
+
+        let mut sm = SourceMap::from_entries(entries);
+        sm.file = self.file.clone();
+        // the sources are still those of `inner`, with its texts and location
+        sm.source_root = inner.source_root.clone();
+        sm.path = inner.path.clone();
+        let inner_index: Vec<Option<usize>> = sm.sources.iter()
+            .map(|s| inner.sources.iter().position(|i| i == s))
+            .collect();
+        sm.original_sources = inner_index.iter().zip(&sm.sources)
+            .map(|(i, s)| i.and_then(|i| inner.original_sources.get(i)).unwrap_or(s).clone())
+            .collect();
+        let contents: Vec<Option<String>> = inner_index.iter()
+            .map(|i| i.and_then(|i| inner.sources_content.get(i).cloned().flatten()))
+            .collect();
+        if contents.iter().any(Option::is_some) {
+            sm.sources_content = contents;
+        }
+        sm
+    }
+
+    /// Serialize as a v3 source map with a single generated line.
+    ///
+    /// Entries are encoded against `sources` and `names`; an entry whose source
+    /// is not listed (or that has no line and column) is written without
+    /// source, and one whose name is not listed without name.
+    pub fn to_json(&self) -> Result<String> {
+        let raw = RawSourceMap {
+            version: 3,
+            file: self.file.clone(),
+            source_root: self.source_root.clone(),
+            sources: self.sources.clone(),
+            sources_content: self.sources_content.clone(),
+            names: self.names.clone(),
+            mappings: Some(encode_mappings(&self.entries, &self.sources, &self.names)),
+            sections: None,
+        };
+        Ok(serde_json::to_string(&raw)?)
+    }
+
+    /// Read and parse a source map file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<SourceMap> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read map file '{}'", path.display()))?;
+        SourceMap::parse_at(&data, Some(path))
+            .with_context(|| format!("Failed to parse '{}'", path.display()))
+    }
+
+    /// Locate and parse the source map of a WebAssembly module.
+    ///
+    /// The map is taken from the module's `sourceMappingURL` section, either an
+    /// embedded `data:` URL or a path relative to the module, falling back to
+    /// `<module>.map` next to the module file (e.g. `program.wasm.map`). If
+    /// no map is found but there is DWARF debug info, the map is built from
+    /// that; a map that is found but cannot be read or decoded is an error.
+    pub fn from_wasm(module: &WasmModule) -> Result<SourceMap> {
+        match locate_map(module) {
+            // embedded maps resolve their sources relative to the module
+            Ok(MapSource::Embedded(json)) => SourceMap::parse_at(&json, module.path())
+                .context("Failed to parse embedded source map"),
+            Ok(MapSource::File(path)) => SourceMap::from_file(path),
+            Err(e) if e.is::<MapNotFound>() && has_dwarf(module) => SourceMap::from_dwarf(module),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Build a map from the DWARF line tables of a module, with the entries
+    /// named after the innermost function and the inlined calls kept for
+    /// [`SourceMap::inlined_calls`].
+    pub fn from_dwarf(module: &WasmModule) -> Result<SourceMap> {
+        let dwarf = read_dwarf(module).context("Failed to read DWARF debug info")?;
+        if dwarf.entries.is_empty() {
+            anyhow::bail!("No line table rows in the module's DWARF debug info");
+        }
+        let mut sm = SourceMap::from_entries(dwarf.entries);
+        sm.path = module.path().map(Path::to_path_buf);
+        sm.inlined = dwarf.inlined;
+        Ok(sm)
+    }
+
+    /// Read a source map file, or a `.wasm` module whose map is located with
+    /// [`SourceMap::from_wasm`].
+    pub fn load(path: impl AsRef<Path>) -> Result<SourceMap> {
+        SourceMap::load_with_module(path).map(|(sm, _)| sm)
+    }
+
+    /// Like [`SourceMap::load`], also returning the module if `path` is one.
+    pub fn load_with_module(path: impl AsRef<Path>) -> Result<(SourceMap, Option<WasmModule>)> {
+        let path = path.as_ref();
+        let mut magic = [0u8; 4];
+        let is_module = fs::File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok_and(|_| is_wasm(&magic));
+        if is_module {
+            let module = WasmModule::from_file(path)?;
+            Ok((SourceMap::from_wasm(&module)?, Some(module)))
+        } else {
+            Ok((SourceMap::from_file(path)?, None))
+        }
+    }
+
+    /// The file this map was read from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Text of `source`, taken from `sourcesContent` or else read from disk
+    /// at its resolved path.
+    pub fn source_text(&self, source: &str) -> Option<String> {
+        let idx = self.sources.iter().position(|s| s == source)?;
+        if let Some(Some(text)) = self.sources_content.get(idx) {
+            return Some(text.clone());
+        }
+        let original = self.original_sources.get(idx)?;
+        if original.contains("://") {
+            return None;
+        }
+        fs::read_to_string(self.resolve_source(original)).ok()
+    }
+
+    /// Resolve a source as in the map per the spec: prefixed with `sourceRoot`,
+    /// relative to the map's location. URLs and absolute paths are kept as is.
+    pub fn resolve_source(&self, source: &str) -> String {
+        let joined = join_source(self.source_root.as_deref().unwrap_or(""), source);
+        if joined.contains("://") || Path::new(&joined).is_absolute() {
+            return joined;
+        }
+        let dir = self.path.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+        normalize_path(&dir.join(joined)).to_string_lossy().into_owned()
+    }
+
+    /// Rename every source, in `sources` and in all entries, e.g. to remap paths
+    /// to a local checkout. Source texts stay readable from their original paths.
+    pub fn map_sources(&mut self, f: impl Fn(&str) -> String) {
+        let renamed: HashMap<String, String> = self.sources.iter()
+            .map(|s| (s.clone(), f(s)))
+            .collect();
+        for e in &mut self.entries {
+            if let Some(source) = &mut e.source
+                && let Some(new) = renamed.get(source.as_str())
+            {
+                *source = new.clone();
+            }
+        }
+        for source in &mut self.sources {
+            *source = renamed[source.as_str()].clone();
+        }
+    }
+
+    /// All decoded entries, in ascending `gen_offset` order.
+    pub fn entries(&self) -> &[MappingEntry] {
+        &self.entries
+    }
+
+    /// The chain of calls inlined at `offset`, innermost first; the first is
+    /// the call of the function the matched entry is in. Only DWARF debug info
+    /// records inlining, so this is empty for source maps.
+    pub fn inlined_calls(&self, offset: u32) -> Vec<InlinedCall> {
+        let mut ranges: Vec<&InlinedRange> = self.inlined.iter()
+            .take_while(|r| r.range.start <= offset)
+            .filter(|r| r.range.contains(&offset))
+            .collect();
+        ranges.sort_by_key(|r| std::cmp::Reverse(r.depth));
+        ranges.into_iter().map(|r| r.call.clone()).collect()
+    }
+
+    /// The entries covering `range`, with consecutive entries from the same
+    /// source line (or consecutive unmapped ones) collapsed into one run.
+    ///
+    /// Runs are clipped to `range`; each keeps the first entry covering it,
+    /// which for the first run may start before `range`.
+    pub fn line_runs(&self, range: Range<u32>) -> Vec<OffsetRange> {
+        let entries = &self.entries;
+        if range.is_empty() {
+            return Vec::new();
+        }
+        // the entry covering range.start, if any, then those starting inside
+        let first = entries.partition_point(|e| e.gen_offset <= range.start).saturating_sub(1);
+        let mut runs: Vec<OffsetRange> = Vec::new();
+        for e in entries[first..].iter().take_while(|e| e.gen_offset < range.end) {
+            let start = e.gen_offset.max(range.start);
+            match runs.last_mut() {
+                Some(run) if run.entry.source == e.source && run.entry.line == e.line => {}
+                Some(run) => {
+                    run.end = Some(start);
+                    runs.push(OffsetRange { start, end: None, entry: e.clone() });
+                }
+                None => runs.push(OffsetRange { start, end: None, entry: e.clone() }),
+            }
+        }
+        if let Some(run) = runs.last_mut() {
+            run.end = Some(range.end);
+        }
+        runs
+    }
+
+    /// Find the entry covering `offset`, or `None` if `offset` precedes every mapping.
+    pub fn lookup(&self, offset: u32) -> Option<Location> {
+        let entries = &self.entries;
+        // bin search for the biggest offset <= target_offset
+        let idx = match entries.binary_search_by(|e| e.gen_offset.cmp(&offset)) {
+            Ok(i) => i,       // precise
+            Err(0) => return None,
+            Err(i) => i - 1,  // not precise, the one before is that <= target
+        };
+        let entry = entries[idx].clone();
+        let closest = if entry.source.is_none() {
+            entries[..idx].iter().rfind(|prev| prev.source.is_some()).cloned()
+        } else {
+            None
+        };
+        Some(Location { query_offset: offset, entry, closest })
+    }
+}
+
+/// Where the source map of a module was found.
+pub(crate) enum MapSource {
+    /// JSON text of a map embedded as a `data:` URL.
+    Embedded(String),
+    File(PathBuf),
+}
+
+/// The error of [`locate_map`] when no map is found, as opposed to one that
+/// is found but cannot be read.
+#[derive(Debug)]
+pub(crate) struct MapNotFound(String);
+
+impl std::fmt::Display for MapNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MapNotFound {}
+
+/// Find the source map of `module`, as described on [`SourceMap::from_wasm`].
+pub(crate) fn locate_map(module: &WasmModule) -> Result<MapSource> {
+    let dir = module.path().and_then(Path::parent);
+    let mut candidates: Vec<PathBuf> = Vec::new();
+
+    if let Some(url) = module.source_mapping_url() {
+        if let Some(data) = url.strip_prefix("data:") {
+            let json = decode_data_url(data)
+                .context("Failed to decode embedded sourceMappingURL data")?;
+            return Ok(MapSource::Embedded(json));
+        }
+        let rel = url.strip_prefix("file://").unwrap_or(url);
+        if !rel.contains("://") {
+            candidates.push(dir.map(|d| d.join(rel)).unwrap_or_else(|| PathBuf::from(rel)));
+        }
+    }
+    if let Some(path) = module.path() {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".map");
+        candidates.push(PathBuf::from(sibling));
+    }
+
+    match candidates.iter().find(|c| c.is_file()) {
+        Some(found) => Ok(MapSource::File(found.clone())),
+        None => {
+            let tried: Vec<String> = candidates.iter().map(|c| format!("'{}'", c.display())).collect();
+            Err(MapNotFound(format!(
+                "No source map found for module (sourceMappingURL: {}, tried: {})",
+                module.source_mapping_url().unwrap_or("none"),
+                if tried.is_empty() { "nothing".to_string() } else { tried.join(", ") }
+            )).into())
+        }
+    }
+}
+
+/// Join `source` onto `root` with a `/`, unless `source` is absolute or a URL.
+fn join_source(root: &str, source: &str) -> String {
+    if root.is_empty() || source.starts_with('/') || source.contains("://") {
+        return source.to_string();
+    }
+    format!("{}/{}", root.trim_end_matches('/'), source)
+}
+
+/// Lexically remove `.` and `..` components, without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(out.components().next_back(), Some(Component::Normal(_))) => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Decode the part of a `data:` URL after the scheme, e.g.
+/// `application/json;base64,eyJ2ZXJzaW9uIjozfQ==`.
+fn decode_data_url(data: &str) -> Result<String> {
+    let (header, payload) = data.split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("Missing ',' in data URL"))?;
+    let bytes = if header.ends_with(";base64") {
+        base64::engine::general_purpose::STANDARD.decode(payload.trim())?
+    } else {
+        percent_decode(payload)
+    };
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = s.get(i + 1..i + 3)
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+        {
+            out.push(b);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Encode sorted `entries` as `mappings`, the inverse of [`decode_mappings`].
+fn encode_mappings(entries: &[MappingEntry], sources: &[String], names: &[String]) -> String {
+    let source_index: HashMap<&str, i64> = sources.iter().enumerate()
+        .rev() // the first of duplicate sources wins
+        .map(|(i, s)| (s.as_str(), i as i64))
+        .collect();
+    let name_index: HashMap<&str, i64> = names.iter().enumerate()
+        .rev()
+        .map(|(i, n)| (n.as_str(), i as i64))
+        .collect();
+
+    let mut out = String::new();
+    let mut gen_offset = 0i64;
+    let mut source = 0i64;
+    let mut original_line = 0i64;
+    let mut original_column = 0i64;
+    let mut name = 0i64;
+
+    for (i, e) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        vlq_encode(e.gen_offset as i64 - gen_offset, &mut out);
+        gen_offset = e.gen_offset as i64;
+
+        let Some(((s, line), column)) = e.source.as_deref()
+            .and_then(|s| source_index.get(s))
+            .zip(e.line)
+            .zip(e.column)
+        else {
+            continue;
+        };
+        // lines are 1-based in entries, 0-based in the map
+        let line = line as i64 - 1;
+        vlq_encode(s - source, &mut out);
+        vlq_encode(line - original_line, &mut out);
+        vlq_encode(column as i64 - original_column, &mut out);
+        (source, original_line, original_column) = (*s, line, column as i64);
+
+        if let Some(&n) = e.name.as_deref().and_then(|n| name_index.get(n)) {
+            vlq_encode(n - name, &mut out);
+            name = n;
+        }
+    }
+    out
+}
+
+/// Decode `mappings`, failing on segments that are not valid VLQ and on
+/// offsets, lines or columns accumulating outside the `u32` range.
+fn decode_mappings(mappings: &str, sources: &[String], names: &[String]) -> Result<Vec<MappingEntry>> {
+    let mut entries: Vec<MappingEntry> = Vec::new();
+
+    let mut gen_offset = 0i64;
+    let mut source_index = 0i64;
+    let mut original_line = 0i64;
+    let mut original_column = 0i64;
+    let mut name_index = 0i64;
+
+    for (l, line) in mappings.split(';').enumerate() {
+        if line.is_empty() { continue; }
+        for (s, segment) in line.split(',').enumerate() {
+            let at = || format!("line {}, segment {}", l + 1, s + 1);
+            let fields = vlq_decode(segment)
+                .map_err(|e| anyhow::anyhow!("Invalid mapping at {}, byte {}: {}", at(), e.position(), e))?;
+            if fields.is_empty() { continue; }
+            let mut idx = 0;
+
+            // generated column (Wasm offset)
+            gen_offset += fields[idx];
+            idx += 1;
+            let offset = u32::try_from(gen_offset)
+                .with_context(|| format!("Generated offset {} out of range at {}", gen_offset, at()))?;
+
+            let mut src = None;
+            let mut orig_line = None;
+            let mut orig_col = None;
+            let mut name = None;
+
+            if fields.len() >= 4 {
+                source_index += fields[idx]; idx += 1;
+                // out of range (or negative) indices leave the entry without source
+                src = usize::try_from(source_index).ok()
+                    .and_then(|i| sources.get(i))
+                    .cloned();
+
+                original_line += fields[idx]; idx += 1;
+                let line = u32::try_from(original_line + 1) // line No. 1-based
+                    .with_context(|| format!("Original line {} out of range at {}", original_line, at()))?;
+                orig_line = Some(line);
+
+                original_column += fields[idx]; idx += 1;
+                let column = u32::try_from(original_column)
+                    .with_context(|| format!("Original column {} out of range at {}", original_column, at()))?;
+                orig_col = Some(column);
+
+                if fields.len() >= 5 {
+                    name_index += fields[idx];
+                    // out of range (or negative) indices leave the entry unnamed
+                    name = usize::try_from(name_index).ok()
+                        .and_then(|i| names.get(i))
+                        .cloned();
+                }
+            }
+
+            entries.push(MappingEntry {
+                gen_offset: offset,
+                source: src,
+                line: orig_line,
+                column: orig_col,
+                name,
+            });
+        }
+    }
+
+    // ascendant
+    entries.sort_by_key(|e| e.gen_offset);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(mappings: &str) -> SourceMap {
+        SourceMap::parse(&format!(
+            r#"{{"version":3,"sources":["a.ts"],"names":[],"mappings":"{}"}}"#, mappings
+        )).unwrap()
+    }
+
+    /// Offset, source, line and column of an entry.
+    type Position<'a> = (u32, Option<&'a str>, Option<u32>, Option<u32>);
+
+    fn position(e: &MappingEntry) -> Position<'_> {
+        (e.gen_offset, e.source.as_deref(), e.line, e.column)
+    }
+
+    // a.ts:1:0 at 10, runtime generated code at 20, a.ts:3:4 at 30
+    const MAPPINGS: &str = "UAAA,U,UAEI";
+
+    #[test]
+    fn lookup_exact() {
+        let loc = map(MAPPINGS).lookup(30).unwrap();
+        assert_eq!(position(&loc.entry), (30, Some("a.ts"), Some(3), Some(4)));
+        assert_eq!(loc.query_offset, 30);
+        assert!(loc.is_exact());
+        assert!(!loc.is_unmapped());
+        assert_eq!(loc.closest, None);
+    }
+
+    #[test]
+    fn lookup_inexact() {
+        let loc = map(MAPPINGS).lookup(15).unwrap();
+        assert_eq!(position(&loc.entry), (10, Some("a.ts"), Some(1), Some(0)));
+        assert!(!loc.is_exact());
+        assert!(!loc.is_unmapped());
+        let loc = map(MAPPINGS).lookup(u32::MAX).unwrap();
+        assert_eq!(loc.entry.gen_offset, 30);
+    }
+
+    #[test]
+    fn lookup_unmapped_with_closest() {
+        let loc = map(MAPPINGS).lookup(25).unwrap();
+        assert_eq!(position(&loc.entry), (20, None, None, None));
+        assert!(!loc.is_exact());
+        assert!(loc.is_unmapped());
+        assert_eq!(loc.closest.as_ref().map(position), Some((10, Some("a.ts"), Some(1), Some(0))));
+    }
+
+    #[test]
+    fn lookup_unmapped_without_closest() {
+        let loc = map("U,UAAA").lookup(10).unwrap();
+        assert!(loc.is_exact());
+        assert!(loc.is_unmapped());
+        assert_eq!(loc.closest, None);
+    }
+
+    #[test]
+    fn lookup_before_first_entry() {
+        assert!(map(MAPPINGS).lookup(9).is_none());
+        assert!(SourceMap::from_entries(Vec::new()).lookup(0).is_none());
+    }
+
+    /// Start, end, first entry offset and line of each run.
+    fn runs(sm: &SourceMap, range: Range<u32>) -> Vec<(u32, Option<u32>, u32, Option<u32>)> {
+        sm.line_runs(range).into_iter().map(|r| (r.start, r.end, r.entry.gen_offset, r.entry.line)).collect()
+    }
+
+    // a.ts:1 at 10 and 12, runtime generated code at 15 and 18, a.ts:3 at 22
+    const RUNS: &str = "UAAA,EAAC,G,G,IAEI";
+
+    #[test]
+    fn line_runs_collapse_lines_and_unmapped_code() {
+        assert_eq!(runs(&map(RUNS), 10..40), [
+            (10, Some(15), 10, Some(1)),
+            (15, Some(22), 15, None),
+            (22, Some(40), 22, Some(3)),
+        ]);
+    }
+
+    #[test]
+    fn line_runs_clip_to_the_range() {
+        assert_eq!(runs(&map(RUNS), 13..20), [(13, Some(15), 12, Some(1)), (15, Some(20), 15, None)]);
+        assert_eq!(runs(&map(RUNS), 25..30), [(25, Some(30), 22, Some(3))]);
+    }
+
+    #[test]
+    fn line_runs_before_the_first_entry() {
+        assert_eq!(runs(&map(RUNS), 0..12), [(10, Some(12), 10, Some(1))]);
+        assert!(runs(&map(RUNS), 0..10).is_empty());
+        assert!(runs(&map(RUNS), 20..20).is_empty());
+        assert!(runs(&SourceMap::from_entries(Vec::new()), 0..10).is_empty());
+    }
+
+    fn entries(sm: &SourceMap) -> Vec<Position<'_>> {
+        sm.entries().iter().map(position).collect()
+    }
+
+    #[test]
+    fn flattens_index_map_sections() {
+        let sm = SourceMap::parse(r#"{"version":3,"sections":[
+            {"offset":{"line":0,"column":100},"map":{"version":3,"sources":["a.ts"],"names":["f"],"mappings":"AAAAA,CACA"}},
+            {"offset":{"line":0,"column":200},"map":{"version":3,"sourceRoot":"src","sources":["b.ts","/abs/c.ts"],"names":["f","g"],"mappings":"AAAAC,CCAAA"}}
+        ]}"#).unwrap();
+        assert_eq!(sm.sources, ["a.ts", "src/b.ts", "/abs/c.ts"]);
+        assert_eq!(sm.names, ["f", "g"]);
+        assert_eq!(entries(&sm), [
+            (100, Some("a.ts"), Some(1), Some(0)),
+            (101, Some("a.ts"), Some(2), Some(0)),
+            (200, Some("src/b.ts"), Some(1), Some(0)),
+            (201, Some("/abs/c.ts"), Some(1), Some(0)),
+        ]);
+        let names: Vec<Option<&str>> = sm.entries().iter().map(|e| e.name.as_deref()).collect();
+        assert_eq!(names, [Some("f"), None, Some("g"), Some("g")]);
+    }
+
+    #[test]
+    fn clips_sections_at_the_next_one() {
+        let sm = SourceMap::parse(r#"{"version":3,"sections":[
+            {"offset":{"line":0,"column":0},"map":{"version":3,"sources":["a.ts"],"mappings":"AAAA,UACA,UACA"}},
+            {"offset":{"line":0,"column":15},"map":{"version":3,"sources":["b.ts"],"mappings":"AAAA"}}
+        ]}"#).unwrap();
+        assert_eq!(entries(&sm), [
+            (0, Some("a.ts"), Some(1), Some(0)),
+            (10, Some("a.ts"), Some(2), Some(0)),
+            (15, Some("b.ts"), Some(1), Some(0)),
+        ]);
+    }
+
+    #[test]
+    fn rejects_unsorted_sections() {
+        for offsets in [(20, 10), (10, 10)] {
+            let json = format!(r#"{{"version":3,"sections":[
+                {{"offset":{{"line":0,"column":{}}},"map":{{"version":3,"sources":["a.ts"],"mappings":"AAAA"}}}},
+                {{"offset":{{"line":0,"column":{}}},"map":{{"version":3,"sources":["b.ts"],"mappings":"AAAA"}}}}
+            ]}}"#, offsets.0, offsets.1);
+            let err = SourceMap::parse(&json).unwrap_err();
+            assert!(format!("{:#}", err).contains("does not start after section 0"), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn merges_sources_content_of_repeated_sources() {
+        let sm = SourceMap::parse(r#"{"version":3,"sections":[
+            {"offset":{"line":0,"column":0},"map":{"version":3,"sources":["a.ts"],"mappings":"AAAA"}},
+            {"offset":{"line":0,"column":10},"map":{"version":3,"sources":["b.ts","a.ts"],"sourcesContent":[null,"let a = 1;"],"mappings":"AAAA,CCAA"}},
+            {"offset":{"line":0,"column":20},"map":{"version":3,"sources":["a.ts"],"sourcesContent":["stale"],"mappings":"AAAA"}}
+        ]}"#).unwrap();
+        assert_eq!(sm.sources, ["a.ts", "b.ts"]);
+        assert_eq!(sm.sources_content, [Some("let a = 1;".to_string()), None]);
+    }
+
+    #[test]
+    fn reads_url_sections_relative_to_the_index_map() {
+        let dir = std::env::temp_dir().join(format!("wasm_map_lookup_sections_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/b.wasm.map"), r#"{"version":3,"sourceRoot":"src","sources":["b.ts"],"mappings":"AAAA"}"#).unwrap();
+        fs::write(dir.join("index.wasm.map"), r#"{"version":3,"sections":[
+            {"offset":{"line":0,"column":0},"map":{"version":3,"sources":["a.ts"],"mappings":"AAAA"}},
+            {"offset":{"line":0,"column":200},"url":"sub/b.wasm.map"}
+        ]}"#).unwrap();
+        let sm = SourceMap::from_file(dir.join("index.wasm.map"));
+        let missing = fs::write(dir.join("index.wasm.map"), r#"{"version":3,"sections":[
+            {"offset":{"line":0,"column":0},"url":"missing.wasm.map"}
+        ]}"#).map(|_| SourceMap::from_file(dir.join("index.wasm.map")));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sm = sm.unwrap();
+        assert_eq!(sm.sources, ["a.ts", "sub/src/b.ts"]);
+        assert_eq!(entries(&sm)[1], (200, Some("sub/src/b.ts"), Some(1), Some(0)));
+        let err = missing.unwrap().unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to load section 0"), "{:#}", err);
+    }
+
+    #[test]
+    fn composes_through_the_inner_map() {
+        // a.ts:1:0 named `inner` at 10, runtime generated code at 20, a.ts:3:4 at 30
+        let inner = SourceMap::parse(r#"{"version":3,"sources":["a.ts"],"sourcesContent":["let a = 1;"],
+            "names":["inner"],"mappings":"UAAAA,U,UAEI"}"#).unwrap();
+        let outer_entry = |gen_offset, column: u32, name: Option<&str>| MappingEntry {
+            gen_offset,
+            source: Some("mid.wasm".to_string()),
+            line: Some(1),
+            column: Some(column),
+            name: name.map(str::to_string),
+        };
+        let mut outer = SourceMap::from_entries(vec![
+            outer_entry(100, 12, Some("outer")),
+            outer_entry(110, 25, None),
+            outer_entry(120, 5, None),
+            outer_entry(130, 31, Some("outer")),
+            MappingEntry { gen_offset: 140, source: None, line: None, column: None, name: None },
+        ]);
+        outer.file = Some("opt.wasm".to_string());
+
+        let sm = outer.compose(&inner);
+        assert_eq!(entries(&sm), [
+            (100, Some("a.ts"), Some(1), Some(0)),
+            // runtime generated code of the intermediate module
+            (110, None, None, None),
+            // before the first mapping of the intermediate module
+            (120, None, None, None),
+            (130, Some("a.ts"), Some(3), Some(4)),
+            (140, None, None, None),
+        ]);
+        let names: Vec<Option<&str>> = sm.entries().iter().map(|e| e.name.as_deref()).collect();
+        assert_eq!(names, [Some("inner"), None, None, Some("outer"), None]);
+        assert_eq!(sm.file.as_deref(), Some("opt.wasm"));
+        assert_eq!(sm.sources, ["a.ts"]);
+        assert_eq!(sm.source_text("a.ts").as_deref(), Some("let a = 1;"));
+    }
+
+    fn map_at(json: &str, path: &str) -> SourceMap {
+        SourceMap::parse_at(json, Some(Path::new(path))).unwrap()
+    }
+
+    #[test]
+    fn resolves_sources_against_the_root_and_the_map() {
+        for root in ["src", "src/"] {
+            let sm = map_at(&format!(
+                r#"{{"version":3,"sourceRoot":"{}","sources":["a.ts"],"mappings":"AAAA"}}"#, root
+            ), "/maps/build/x.wasm.map");
+            assert_eq!(sm.resolve_source("a.ts"), "/maps/build/src/a.ts");
+            assert_eq!(sm.resolve_source("../../lib/b.ts"), "/maps/lib/b.ts");
+            assert_eq!(sm.resolve_source("/abs/c.ts"), "/abs/c.ts");
+            assert_eq!(sm.resolve_source("webpack://app/d.ts"), "webpack://app/d.ts");
+        }
+        let sm = map_at(r#"{"version":3,"sources":["a.ts"],"mappings":"AAAA"}"#, "build/x.wasm.map");
+        assert_eq!(sm.resolve_source("./a.ts"), "build/a.ts");
+        assert_eq!(sm.resolve_source("../../a.ts"), "../a.ts");
+        assert_eq!(map(MAPPINGS).resolve_source("./a.ts"), "a.ts");
+    }
+
+    #[test]
+    fn joins_and_normalizes_paths() {
+        assert_eq!(join_source("", "a.ts"), "a.ts");
+        assert_eq!(join_source("src", "a.ts"), "src/a.ts");
+        assert_eq!(join_source("src/", "a.ts"), "src/a.ts");
+        assert_eq!(join_source("src", "/abs/a.ts"), "/abs/a.ts");
+        assert_eq!(join_source("src", "file:///abs/a.ts"), "file:///abs/a.ts");
+        assert_eq!(join_source("http://host/src/", "a.ts"), "http://host/src/a.ts");
+
+        assert_eq!(normalize_path(Path::new("a/./b/../c.ts")), Path::new("a/c.ts"));
+        assert_eq!(normalize_path(Path::new("a/../../b.ts")), Path::new("../b.ts"));
+        assert_eq!(normalize_path(Path::new("../../b.ts")), Path::new("../../b.ts"));
+        assert_eq!(normalize_path(Path::new("/a/b/../../c.ts")), Path::new("/c.ts"));
+    }
+
+    const JSON: &str = r#"{"version":3,"sources":["a.ts"],"mappings":"AAAA"}"#;
+
+    fn module_with_url(url: &str) -> WasmModule {
+        let section = crate::wasm::testing::source_mapping_url(url);
+        crate::wasm::testing::module_with_custom(&[], &[&[0x0b]], &[("sourceMappingURL", &section)])
+    }
+
+    #[test]
+    fn decodes_data_urls() {
+        let base64 = base64::engine::general_purpose::STANDARD.encode(JSON);
+        assert_eq!(decode_data_url(&format!("application/json;base64,{}", base64)).unwrap(), JSON);
+        assert_eq!(decode_data_url(&format!("application/json;charset=utf-8;base64, {}\n", base64)).unwrap(), JSON);
+        assert_eq!(decode_data_url("application/json,%7B%22version%22:3%7D").unwrap(), r#"{"version":3}"#);
+        assert_eq!(decode_data_url(",%E2%9C%93").unwrap(), "\u{2713}");
+        assert!(decode_data_url("application/json;base64").is_err());
+        assert!(decode_data_url("application/json;base64,!!").is_err());
+        assert!(decode_data_url(",%FF").is_err());
+
+        assert_eq!(percent_decode("a%20b%2fc"), b"a b/c");
+        // malformed escapes are kept as is
+        assert_eq!(percent_decode("100%"), b"100%");
+        assert_eq!(percent_decode("%zz%4"), b"%zz%4");
+    }
+
+    #[test]
+    fn reads_maps_embedded_in_the_module() {
+        let base64 = base64::engine::general_purpose::STANDARD.encode(JSON);
+        let escaped = JSON.replace('{', "%7B").replace('}', "%7D").replace('"', "%22");
+        for url in [format!("data:application/json;base64,{}", base64), format!("data:application/json,{}", escaped)] {
+            let module = module_with_url(&url);
+            assert!(matches!(locate_map(&module).unwrap(), MapSource::Embedded(json) if json == JSON), "{}", url);
+            assert_eq!(SourceMap::from_wasm(&module).unwrap().sources, ["a.ts"]);
+        }
+        let err = locate_map(&module_with_url("data:application/json;base64")).err().unwrap();
+        assert!(!err.is::<MapNotFound>());
+    }
+
+    #[test]
+    fn finds_map_files_next_to_the_module() {
+        let dir = std::env::temp_dir().join(format!("wasm_map_lookup_locate_{}", std::process::id()));
+        fs::create_dir_all(dir.join("maps")).unwrap();
+        let write_module = |name: &str, url: Option<&str>| {
+            let module = match url {
+                Some(url) => module_with_url(url),
+                None => crate::wasm::testing::module(&[], &[&[0x0b]]),
+            };
+            fs::write(dir.join(name), module.bytes()).unwrap();
+            WasmModule::from_file(dir.join(name)).unwrap()
+        };
+        let located = |module: &WasmModule| match locate_map(module) {
+            Ok(MapSource::File(path)) => Ok(path),
+            Ok(MapSource::Embedded(_)) => panic!("embedded map"),
+            Err(e) => Err(e),
+        };
+        fs::write(dir.join("maps/x.map"), JSON).unwrap();
+        fs::write(dir.join("x.wasm.map"), JSON).unwrap();
+
+        let by_url = located(&write_module("x.wasm", Some("maps/x.map")));
+        let by_file_url = located(&write_module("x.wasm", Some("file://maps/x.map")));
+        let sibling = located(&write_module("x.wasm", Some("missing.map")));
+        let remote = located(&write_module("x.wasm", Some("http://host/x.wasm.map")));
+        let no_url = located(&write_module("x.wasm", None));
+        let missing = located(&write_module("y.wasm", Some("missing.map")));
+        let in_memory = locate_map(&module_with_url("missing.map"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(by_url.unwrap(), dir.join("maps/x.map"));
+        assert_eq!(by_file_url.unwrap(), dir.join("maps/x.map"));
+        assert_eq!(sibling.unwrap(), dir.join("x.wasm.map"));
+        assert_eq!(remote.unwrap(), dir.join("x.wasm.map"));
+        assert_eq!(no_url.unwrap(), dir.join("x.wasm.map"));
+        let err = missing.unwrap_err();
+        assert!(err.is::<MapNotFound>());
+        let tried = format!("tried: '{}', '{}'", dir.join("missing.map").display(), dir.join("y.wasm.map").display());
+        assert!(err.to_string().contains(&tried), "{}", err);
+        let err = in_memory.err().unwrap();
+        assert!(err.to_string().contains("tried: 'missing.map'"), "{}", err);
+    }
+}
@@ -0,0 +1,245 @@
+//! How much of a module maps back to TS: byte counts per source, function and line.
+//!
+//! Each entry is taken to cover the bytes up to the next entry's offset; the
+//! last one up to the end of the code section if the module is known, else
+//! it covers nothing.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::sourcemap::{MappingEntry, SourceMap};
+use crate::wasm::WasmModule;
+
+/// Generated bytes attributed to one source file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceStats {
+    pub source: String,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Size of one function body and how much of it is mapped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunctionStats {
+    pub index: u32,
+    pub name: Option<String>,
+    pub size: u32,
+    pub mapped_bytes: u64,
+}
+
+/// Consecutive bytes covered by unmapped (runtime generated) entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnmappedRun {
+    pub start: u32,
+    /// Exclusive.
+    pub end: u32,
+    /// The closest preceding entry with a source.
+    pub closest: Option<MappingEntry>,
+}
+
+/// Generated bytes attributed to one source line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineStats {
+    pub source: String,
+    pub line: u32,
+    pub bytes: u64,
+}
+
+/// Coverage statistics of a map, optionally against its module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MapStats {
+    pub entries: usize,
+    /// Entries with a source, i.e. segments with 4 or 5 fields.
+    pub mapped_entries: usize,
+    /// Single-field segments.
+    pub unmapped_entries: usize,
+    pub mapped_bytes: u64,
+    pub unmapped_bytes: u64,
+    /// All sources, by descending byte count.
+    pub sources: Vec<SourceStats>,
+    /// All function bodies, by descending size; only with the module.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub functions: Option<Vec<FunctionStats>>,
+    /// The `top` largest unmapped runs.
+    pub unmapped_runs: Vec<UnmappedRun>,
+    /// The `top` source lines generating the most bytes.
+    pub top_lines: Vec<LineStats>,
+}
+
+impl MapStats {
+    pub fn new(map: &SourceMap, module: Option<&WasmModule>, top: usize) -> MapStats {
+        let entries = map.entries();
+        let end = module.and_then(|m| m.code_section()).map(|code| code.end);
+        let ranges: Vec<Range<u32>> = entries.iter().enumerate()
+            .map(|(i, e)| {
+                let next = entries.get(i + 1).map(|n| n.gen_offset).or(end).unwrap_or(e.gen_offset);
+                e.gen_offset..next.max(e.gen_offset)
+            })
+            .collect();
+
+        let mut stats = MapStats {
+            entries: entries.len(),
+            mapped_entries: 0,
+            unmapped_entries: 0,
+            mapped_bytes: 0,
+            unmapped_bytes: 0,
+            sources: Vec::new(),
+            functions: None,
+            unmapped_runs: Vec::new(),
+            top_lines: Vec::new(),
+        };
+        let mut sources: HashMap<&str, SourceStats> = HashMap::new();
+        let mut lines: HashMap<(&str, u32), u64> = HashMap::new();
+        let mut closest: Option<&MappingEntry> = None;
+
+        for (e, range) in entries.iter().zip(&ranges) {
+            let bytes = range.len() as u64;
+            let Some(source) = e.source.as_deref() else {
+                stats.unmapped_entries += 1;
+                stats.unmapped_bytes += bytes;
+                match stats.unmapped_runs.last_mut() {
+                    Some(run) if run.end == range.start && run.closest.as_ref() == closest => run.end = range.end,
+                    _ if bytes > 0 => stats.unmapped_runs.push(UnmappedRun {
+                        start: range.start,
+                        end: range.end,
+                        closest: closest.cloned(),
+                    }),
+                    _ => {}
+                }
+                continue;
+            };
+            closest = Some(e);
+            stats.mapped_entries += 1;
+            stats.mapped_bytes += bytes;
+            let s = sources.entry(source)
+                .or_insert_with(|| SourceStats { source: source.to_string(), entries: 0, bytes: 0 });
+            s.entries += 1;
+            s.bytes += bytes;
+            if let Some(line) = e.line {
+                *lines.entry((source, line)).or_default() += bytes;
+            }
+        }
+
+        stats.sources = sources.into_values().collect();
+        stats.sources.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.source.cmp(&b.source)));
+
+        stats.unmapped_runs.sort_by(|a, b| (b.end - b.start).cmp(&(a.end - a.start)).then(a.start.cmp(&b.start)));
+        stats.unmapped_runs.truncate(top);
+
+        stats.top_lines = lines.into_iter()
+            .map(|((source, line), bytes)| LineStats { source: source.to_string(), line, bytes })
+            .collect();
+        stats.top_lines.sort_by(|a, b| {
+            b.bytes.cmp(&a.bytes).then_with(|| a.source.cmp(&b.source)).then(a.line.cmp(&b.line))
+        });
+        stats.top_lines.truncate(top);
+
+        stats.functions = module.map(|module| {
+            let mut functions: Vec<FunctionStats> = module.functions().iter().map(|f| FunctionStats {
+                index: f.index,
+                name: f.name.clone(),
+                size: f.body.len() as u32,
+                mapped_bytes: mapped_bytes_in(entries, &ranges, &f.body),
+            }).collect();
+            functions.sort_by(|a, b| b.size.cmp(&a.size).then(a.index.cmp(&b.index)));
+            functions
+        });
+        stats
+    }
+}
+
+/// Bytes of `body` covered by entries with a source.
+fn mapped_bytes_in(entries: &[MappingEntry], ranges: &[Range<u32>], body: &Range<u32>) -> u64 {
+    let first = ranges.partition_point(|r| r.end <= body.start);
+    entries[first..].iter().zip(&ranges[first..])
+        .take_while(|(_, r)| r.start < body.end)
+        .filter(|(e, _)| e.source.is_some())
+        .map(|(_, r)| (r.end.min(body.end).saturating_sub(r.start.max(body.start))) as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::testing;
+
+    fn entry(gen_offset: u32, source: &str, line: u32) -> MappingEntry {
+        MappingEntry { gen_offset, source: Some(source.to_string()), line: Some(line), column: Some(0), name: None }
+    }
+
+    fn unmapped(gen_offset: u32) -> MappingEntry {
+        MappingEntry { gen_offset, source: None, line: None, column: None, name: None }
+    }
+
+    /// Two functions with bodies at 38..44 and 45..51, the code section ending at 51.
+    fn module() -> WasmModule {
+        testing::module(&["abort"], &[&[0x01, 0x01, 0x01, 0x01, 0x0b], &[0x01, 0x01, 0x01, 0x01, 0x0b]])
+    }
+
+    fn map() -> SourceMap {
+        SourceMap::from_entries(vec![
+            entry(38, "a.ts", 1),
+            unmapped(41),
+            unmapped(43),
+            entry(46, "b.ts", 1),
+            unmapped(47),
+            entry(48, "a.ts", 2),
+        ])
+    }
+
+    fn runs(stats: &MapStats) -> Vec<(u32, u32, Option<u32>)> {
+        stats.unmapped_runs.iter().map(|r| (r.start, r.end, r.closest.as_ref().map(|e| e.gen_offset))).collect()
+    }
+
+    #[test]
+    fn attributes_bytes_up_to_the_end_of_the_code_section() {
+        let module = module();
+        assert_eq!(module.code_section(), Some(36..51));
+        let stats = MapStats::new(&map(), Some(&module), 10);
+        assert_eq!((stats.entries, stats.mapped_entries, stats.unmapped_entries), (6, 3, 3));
+        assert_eq!((stats.mapped_bytes, stats.unmapped_bytes), (7, 6));
+        assert_eq!(stats.sources, [
+            SourceStats { source: "a.ts".to_string(), entries: 2, bytes: 6 },
+            SourceStats { source: "b.ts".to_string(), entries: 1, bytes: 1 },
+        ]);
+        let lines: Vec<(&str, u32, u64)> = stats.top_lines.iter().map(|l| (l.source.as_str(), l.line, l.bytes)).collect();
+        assert_eq!(lines, [("a.ts", 1, 3), ("a.ts", 2, 3), ("b.ts", 1, 1)]);
+    }
+
+    #[test]
+    fn attributes_nothing_to_the_last_entry_without_a_module() {
+        let stats = MapStats::new(&map(), None, 10);
+        assert_eq!((stats.mapped_bytes, stats.unmapped_bytes), (4, 6));
+        assert_eq!(stats.functions, None);
+        let lines: Vec<(&str, u32, u64)> = stats.top_lines.iter().map(|l| (l.source.as_str(), l.line, l.bytes)).collect();
+        assert_eq!(lines, [("a.ts", 1, 3), ("b.ts", 1, 1), ("a.ts", 2, 0)]);
+    }
+
+    #[test]
+    fn merges_unmapped_runs_with_the_same_closest_entry() {
+        let stats = MapStats::new(&map(), None, 10);
+        assert_eq!(runs(&stats), [(41, 46, Some(38)), (47, 48, Some(46))]);
+        assert_eq!(runs(&MapStats::new(&map(), None, 1)), [(41, 46, Some(38))]);
+        let stats = MapStats::new(&SourceMap::from_entries(vec![unmapped(10), unmapped(12), entry(20, "a.ts", 1)]), None, 10);
+        assert_eq!(runs(&stats), [(10, 20, None)]);
+    }
+
+    #[test]
+    fn counts_mapped_bytes_per_function() {
+        let module = module();
+        let stats = MapStats::new(&map(), Some(&module), 10);
+        let functions: Vec<(u32, u32, u64)> = stats.functions.unwrap().iter().map(|f| (f.index, f.size, f.mapped_bytes)).collect();
+        assert_eq!(functions, [(1, 6, 3), (2, 6, 4)]);
+    }
+
+    #[test]
+    fn clips_entries_to_the_body() {
+        let map = map();
+        let ranges = [38..41, 41..43, 43..46, 46..47, 47..48, 48..51];
+        assert_eq!(mapped_bytes_in(map.entries(), &ranges, &(40..47)), 2);
+        assert_eq!(mapped_bytes_in(map.entries(), &ranges, &(41..46)), 0);
+        assert_eq!(mapped_bytes_in(map.entries(), &ranges, &(49..60)), 2);
+        assert_eq!(mapped_bytes_in(map.entries(), &ranges, &(0..38)), 0);
+    }
+}
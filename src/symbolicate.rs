@@ -0,0 +1,303 @@
+//! Rewriting of Wasm frames in runtime stack traces with their TS source positions.
+//!
+//! Recognized frame formats, all carrying module-absolute offsets:
+//!
+//! - V8 / Node and SpiderMonkey: `at wasm://wasm/abcd:wasm-function[3]:0x1a2b`,
+//!   `at calculate (wasm://wasm/abcd:wasm-function[3]:0x1a2b)`
+//! - Wasmer: `at <unnamed> (<module>[3]:0x1a2b)`
+//! - wasmtime: `0:   0x1a2b - <unknown>!<wasm function 3>`
+//! - Bare offsets: `at <unknown>@0x1234`
+
+use regex::{Captures, Regex};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use crate::sourcemap::{MappingEntry, SourceMap};
+use crate::wasm::WasmModule;
+
+/// `name (location[N]:0xOFF)` or `[name@]location[N]:0xOFF`, the location
+/// being `[url:]wasm-function` or Wasmer's `<module>`.
+static FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(concat!(
+    r"(?P<na>[^\s()]+) \((?:(?:[^\s()]*:)?wasm-function|<module>)\[(?P<ia>\d+)\]:0x(?P<a>[0-9a-fA-F]+)\)",
+    r"|(?:(?P<nb>[^\s()@]+)@)?(?:(?:[^\s()@]*:)?wasm-function|<module>)\[(?P<ib>\d+)\]:0x(?P<b>[0-9a-fA-F]+)",
+)).unwrap());
+
+/// `at name@0xOFF`, only as a whole frame line since `word@0xOFF` is common in other text.
+static BARE_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*at\s+(?P<frame>(?P<name>[^\s()@]*)@0x(?P<off>[0-9a-fA-F]+))\s*$").unwrap()
+});
+
+/// wasmtime's `  N:   0xOFF - module!function`.
+static WASMTIME_FRAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*\d+:\s+0x(?P<off>[0-9a-fA-F]+) - (?P<frame>(?:[^!]*!)?(?P<name>.*))$").unwrap()
+});
+
+/// `wasm-function[N]` without an offset, as in function-level profiles.
+static FUNCTION_FRAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"wasm-function\[(?P<index>\d+)\]").unwrap());
+
+/// A Wasm frame recognized in a line of a stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Module-absolute offset of the frame.
+    pub offset: u32,
+    /// Byte range of the line that describes the frame location, and is replaced.
+    pub span: Range<usize>,
+    /// Function name printed by the runtime, unless it is a placeholder like `<unknown>`.
+    pub name: Option<String>,
+    /// Function index printed by the runtime, e.g. `N` of `wasm-function[N]`.
+    pub index: Option<u32>,
+}
+
+/// Find all Wasm frames in `line`.
+pub fn find_frames(line: &str) -> Vec<Frame> {
+    if let Some(caps) = WASMTIME_FRAME.captures(line).or_else(|| BARE_FRAME.captures(line)) {
+        let span = caps.name("frame").unwrap().range();
+        return parse_hex(&caps["off"])
+            .map(|offset| Frame { offset, span, name: runtime_name(&caps["name"]).map(str::to_string), index: None })
+            .into_iter()
+            .collect();
+    }
+    FRAME.captures_iter(line).filter_map(|caps: Captures| {
+        let off = caps.name("a").or_else(|| caps.name("b"))?;
+        let offset = parse_hex(off.as_str())?;
+        let name = caps.name("na").or_else(|| caps.name("nb"));
+        let name = name.and_then(|m| runtime_name(m.as_str())).map(str::to_string);
+        let index = caps.name("ia").or_else(|| caps.name("ib")).and_then(|m| m.as_str().parse().ok());
+        Some(Frame { offset, span: caps.get(0).unwrap().range(), name, index })
+    }).collect()
+}
+
+/// A function name given by a runtime or profiler, unless it is a placeholder
+/// like `<unknown>` or `(program)`.
+pub(crate) fn runtime_name(name: &str) -> Option<&str> {
+    (!name.is_empty() && !name.starts_with(['<', '('])).then_some(name)
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// The function and TS position of the code at an offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub function: Option<String>,
+    /// The TS position, or for runtime generated code the closest preceding one.
+    pub position: Option<MappingEntry>,
+    pub runtime_generated: bool,
+}
+
+impl FrameInfo {
+    /// `function (file:line)`, the compact form used for profile frames, or
+    /// `None` if nothing is known.
+    pub fn label(&self) -> Option<String> {
+        let position = self.position.as_ref()
+            .and_then(|e| Some(format!("{}:{}", e.source.as_deref()?, e.line?)));
+        match (&self.function, position) {
+            (None, None) => None,
+            (Some(name), None) => Some(name.clone()),
+            (name, Some(position)) => {
+                Some(format!("{} ({})", name.as_deref().unwrap_or("<unknown>"), position))
+            }
+        }
+    }
+}
+
+/// Rewrites stack trace frames using a source map and optionally the module.
+pub struct Symbolicator<'a> {
+    map: &'a SourceMap,
+    module: Option<&'a WasmModule>,
+}
+
+impl<'a> Symbolicator<'a> {
+    pub fn new(map: &'a SourceMap, module: Option<&'a WasmModule>) -> Symbolicator<'a> {
+        Symbolicator { map, module }
+    }
+
+    /// What is known about the code at `offset`.
+    ///
+    /// The function is named from the module's name section if available, else
+    /// from `runtime_name`, else from the map's `names`.
+    pub fn resolve(&self, offset: u32, runtime_name: Option<&str>) -> FrameInfo {
+        let function = self.module
+            .and_then(|m| m.function_at(offset))
+            .and_then(|f| f.name.clone())
+            .or_else(|| runtime_name.map(str::to_string));
+        let Some(loc) = self.map.lookup(offset) else {
+            return FrameInfo { function, position: None, runtime_generated: false };
+        };
+        let runtime_generated = loc.is_unmapped();
+        let position = match runtime_generated {
+            true => loc.closest,
+            false => Some(loc.entry.clone()),
+        };
+        FrameInfo { function: function.or(loc.entry.name), position, runtime_generated }
+    }
+
+    /// Describe `offset` as `function (file:line:col)`, or `None` if nothing is known.
+    pub fn describe(&self, offset: u32, runtime_name: Option<&str>) -> Option<String> {
+        let info = self.resolve(offset, runtime_name);
+        let position = info.position.as_ref().map(|e| match info.runtime_generated {
+            true => format!("{} [runtime generated]", e.position()),
+            false => e.position(),
+        });
+        match (info.function, position) {
+            (None, None) => None,
+            (name, None) => name,
+            (name, Some(position)) => {
+                Some(format!("{} ({})", name.as_deref().unwrap_or("<unknown>"), position))
+            }
+        }
+    }
+
+    /// Module-absolute offset of a frame as printed by a runtime or profiler,
+    /// with the function name it gives. Besides the forms of [`find_frames`],
+    /// `wasm-function[N]` without an offset stands for the first mapped offset
+    /// in the body of function N (or its start), if the module is known.
+    pub fn frame_offset(&self, frame: &str) -> Option<(u32, Option<String>)> {
+        if let Some(frame) = find_frames(frame).into_iter().next() {
+            return self.accepts(&frame).then_some((frame.offset, frame.name));
+        }
+        let index = FUNCTION_FRAME.captures(frame)?["index"].parse().ok()?;
+        let body = &self.module?.function(index)?.body;
+        let entries = self.map.entries();
+        let first = entries[entries.partition_point(|e| e.gen_offset < body.start)..].iter()
+            .take_while(|e| e.gen_offset < body.end)
+            .find(|e| e.source.is_some());
+        Some((first.map_or(body.start, |e| e.gen_offset), None))
+    }
+
+    /// Rewrite each recognized frame of `line`; other text is left untouched.
+    pub fn symbolicate_line(&self, line: &str) -> String {
+        let mut out = String::with_capacity(line.len());
+        let mut last = 0;
+        for frame in find_frames(line).into_iter().filter(|f| self.accepts(f)) {
+            if let Some(desc) = self.describe(frame.offset, frame.name.as_deref()) {
+                out.push_str(&line[last..frame.span.start]);
+                out.push_str(&desc);
+                last = frame.span.end;
+            }
+        }
+        out.push_str(&line[last..]);
+        out
+    }
+
+    /// Whether `frame` can be a frame of the module: if the module is known,
+    /// inside its code section and, if the frame gives one, the body of the
+    /// function it names; else not before the first mapping.
+    fn accepts(&self, frame: &Frame) -> bool {
+        match self.module {
+            Some(module) => match frame.index {
+                Some(index) => module.function(index).is_some_and(|f| f.body.contains(&frame.offset)),
+                None => module.code_section().is_some_and(|code| code.contains(&frame.offset)),
+            },
+            None => self.map.entries().first().is_some_and(|e| e.gen_offset <= frame.offset),
+        }
+    }
+
+    /// Symbolicate a whole trace, writing each line as soon as it is read.
+    pub fn symbolicate<R: BufRead, W: Write>(&self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            writeln!(output, "{}", self.symbolicate_line(&line?))?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(line: &str) -> Option<(u32, &str, Option<String>)> {
+        let frames = find_frames(line);
+        assert!(frames.len() <= 1, "{:?}", frames);
+        frames.into_iter().next().map(|f| (f.offset, &line[f.span], f.name))
+    }
+
+    fn entry(gen_offset: u32, line: u32) -> MappingEntry {
+        MappingEntry { gen_offset, source: Some("a.ts".to_string()), line: Some(line), column: Some(0), name: None }
+    }
+
+    #[test]
+    fn v8_frames() {
+        assert_eq!(
+            frame("    at wasm://wasm/abcd:wasm-function[3]:0x1a2b"),
+            Some((0x1a2b, "wasm://wasm/abcd:wasm-function[3]:0x1a2b", None)),
+        );
+        assert_eq!(
+            frame("    at calculate (wasm://wasm/abcd:wasm-function[3]:0x1a2b)"),
+            Some((0x1a2b, "calculate (wasm://wasm/abcd:wasm-function[3]:0x1a2b)", Some("calculate".to_string()))),
+        );
+    }
+
+    #[test]
+    fn firefox_frames() {
+        assert_eq!(
+            frame("calculate@http://host/program.wasm:wasm-function[1]:0x4d"),
+            Some((0x4d, "calculate@http://host/program.wasm:wasm-function[1]:0x4d", Some("calculate".to_string()))),
+        );
+    }
+
+    #[test]
+    fn wasmer_frames() {
+        assert_eq!(
+            frame("    at <unnamed> (<module>[3]:0x1a2b)"),
+            Some((0x1a2b, "<unnamed> (<module>[3]:0x1a2b)", None)),
+        );
+    }
+
+    #[test]
+    fn wasmtime_frames() {
+        assert_eq!(
+            frame("   0:   0x1a2b - <unknown>!<wasm function 3>"),
+            Some((0x1a2b, "<unknown>!<wasm function 3>", None)),
+        );
+        assert_eq!(
+            frame("   1:     0x4d - m!calculate"),
+            Some((0x4d, "m!calculate", Some("calculate".to_string()))),
+        );
+    }
+
+    #[test]
+    fn bare_frames() {
+        assert_eq!(frame("    at <unknown>@0x1234"), Some((0x1234, "<unknown>@0x1234", None)));
+        assert_eq!(frame("at calculate@0x4d"), Some((0x4d, "calculate@0x4d", Some("calculate".to_string()))));
+    }
+
+    #[test]
+    fn non_frames() {
+        assert_eq!(frame("plain line email me@0xfeed"), None);
+        assert_eq!(frame("    at me@0xfeed and more"), None);
+        assert_eq!(frame("    at main (/app/index.js:12:5)"), None);
+        assert_eq!(frame("Error: offset 0x1a2b out of bounds"), None);
+        assert_eq!(frame("wasm-function[3]"), None);
+        assert_eq!(frame("some text arr[2]:0x20 here"), None);
+        assert_eq!(frame("see (table[2]:0x20)"), None);
+        assert_eq!(frame(""), None);
+    }
+
+    #[test]
+    fn offsets_before_the_map_are_left_alone() {
+        let map = SourceMap::from_entries(vec![entry(0x40, 1), entry(0x50, 2)]);
+        let symbolicator = Symbolicator::new(&map, None);
+        assert_eq!(symbolicator.symbolicate_line("    at <unknown>@0x10"), "    at <unknown>@0x10");
+        assert_eq!(symbolicator.symbolicate_line("    at <unknown>@0x52"), "    at <unknown> (a.ts:2:0)");
+    }
+
+    #[test]
+    fn frames_must_lie_in_the_function_they_name() {
+        let module = crate::wasm::testing::module(&["abort"], &[&[0x01, 0x01, 0x0b], &[0x01, 0x01, 0x0b]]);
+        let body = module.function(2).unwrap().body.clone();
+        let map = SourceMap::from_entries(vec![entry(module.function(1).unwrap().body.start, 1), entry(body.start, 2)]);
+        let symbolicator = Symbolicator::new(&map, Some(&module));
+        let line = format!("    at wasm://wasm/abcd:wasm-function[2]:0x{:x}", body.start);
+        assert_eq!(symbolicator.symbolicate_line(&line), "    at <unknown> (a.ts:2:0)");
+        let line = format!("    at wasm://wasm/abcd:wasm-function[1]:0x{:x}", body.start);
+        assert_eq!(symbolicator.symbolicate_line(&line), line);
+        let line = format!("    at <unnamed> (<module>[0]:0x{:x})", body.start);
+        assert_eq!(symbolicator.symbolicate_line(&line), line);
+        assert_eq!(symbolicator.frame_offset(&format!("wasm-function[2]:0x{:x}", body.start)), Some((body.start, None)));
+        assert_eq!(symbolicator.frame_offset(&format!("wasm-function[1]:0x{:x}", body.start)), None);
+    }
+}
@@ -0,0 +1,414 @@
+//! Strict checking of source maps, diagnosing what the lenient parser skips.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::sourcemap::{locate_map, MapSource, MAX_SECTION_DEPTH};
+use crate::vlq::{vlq_decode, VlqError};
+use crate::wasm::{is_wasm, WasmModule};
+
+/// What is wrong with a source map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The file is not valid JSON, or not shaped like a source map.
+    Malformed(String),
+    /// `version` is missing or not `3`.
+    Version(String),
+    /// A segment that is not valid base64 VLQ.
+    Vlq(VlqError),
+    /// A segment with other than 1, 4 or 5 fields.
+    FieldCount(usize),
+    /// An accumulated field went below zero.
+    Negative { field: &'static str, value: i64 },
+    /// An accumulated index points past the end of `sources` or `names`,
+    /// or an offset past `u32::MAX`.
+    OutOfRange { field: &'static str, value: i64, limit: i64 },
+    /// A generated offset lower than the previous one.
+    Unsorted { offset: i64, previous: i64 },
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemKind::Malformed(msg) => write!(f, "{}", msg),
+            ProblemKind::Version(v) => write!(f, "version is {}, expected 3", v),
+            ProblemKind::Vlq(e) => write!(f, "{}", e),
+            ProblemKind::FieldCount(n) => write!(f, "segment has {} fields, expected 1, 4 or 5", n),
+            ProblemKind::Negative { field, value } => write!(f, "{} is negative ({})", field, value),
+            ProblemKind::OutOfRange { field, value, limit } => {
+                write!(f, "{} {} is out of range (must be below {})", field, value, limit)
+            }
+            ProblemKind::Unsorted { offset, previous } => {
+                write!(f, "generated offset {:#x} is lower than the previous {:#x}", offset, previous)
+            }
+        }
+    }
+}
+
+/// Where in `mappings` a problem was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPosition {
+    /// 1-based generated line, i.e. `;`-separated group.
+    pub line: usize,
+    /// 1-based segment within the line.
+    pub segment: usize,
+    /// 0-based byte offset of the segment (or the offending character) in `mappings`.
+    pub column: usize,
+}
+
+/// One problem found in a source map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Path to the index map section the problem is in, e.g. `sections[2]`;
+    /// empty for the top-level map.
+    pub section: String,
+    pub position: Option<SegmentPosition>,
+    pub kind: ProblemKind,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.section.is_empty() {
+            write!(f, "{}: ", self.section)?;
+        }
+        if let Some(pos) = self.position {
+            write!(f, "line {}, segment {} (char {}): ", pos.line, pos.segment, pos.column)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// Check the source map JSON `data`, or, if `data` is an index map, all its sections.
+///
+/// Sections referenced by `url` are read relative to the directory of `path`.
+pub fn validate(data: &str, path: Option<&Path>) -> Vec<Problem> {
+    let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+    let mut validator = Validator { problems: Vec::new(), section: String::new() };
+    match serde_json::from_str::<Value>(data) {
+        Ok(map) => validator.check_map(&map, dir, 0),
+        Err(e) => validator.report(None, ProblemKind::Malformed(format!("invalid JSON: {}", e))),
+    }
+    validator.problems
+}
+
+/// Check a source map file, or the map of a `.wasm` module located as by
+/// [`SourceMap::from_wasm`](crate::SourceMap::from_wasm).
+pub fn validate_file(path: impl AsRef<Path>) -> Result<Vec<Problem>> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    if is_wasm(&bytes) {
+        let module = WasmModule::from_file(path)?;
+        return match locate_map(&module)? {
+            MapSource::Embedded(json) => Ok(validate(&json, Some(path))),
+            MapSource::File(map_path) => validate_file(map_path),
+        };
+    }
+    match String::from_utf8(bytes) {
+        Ok(data) => Ok(validate(&data, Some(path))),
+        Err(_) => Ok(vec![Problem {
+            section: String::new(),
+            position: None,
+            kind: ProblemKind::Malformed("file is not UTF-8 text".to_string()),
+        }]),
+    }
+}
+
+struct Validator {
+    problems: Vec<Problem>,
+    /// Path of the section being checked.
+    section: String,
+}
+
+impl Validator {
+    fn report(&mut self, position: Option<SegmentPosition>, kind: ProblemKind) {
+        self.problems.push(Problem { section: self.section.clone(), position, kind });
+    }
+
+    fn malformed(&mut self, msg: impl Into<String>) {
+        self.report(None, ProblemKind::Malformed(msg.into()));
+    }
+
+    fn check_map(&mut self, map: &Value, dir: &Path, depth: usize) {
+        let Some(obj) = map.as_object() else {
+            return self.malformed("source map is not a JSON object");
+        };
+        match obj.get("version") {
+            Some(v) if v.as_u64() == Some(3) => {}
+            Some(v) => self.report(None, ProblemKind::Version(v.to_string())),
+            None => self.report(None, ProblemKind::Version("missing".to_string())),
+        }
+        let sources = self.string_array_len(obj.get("sources"), "sources");
+        let names = self.string_array_len(obj.get("names"), "names");
+
+        match (obj.get("mappings"), obj.get("sections")) {
+            (Some(Value::String(mappings)), None) => self.check_mappings(mappings, sources, names),
+            (Some(_), None) => self.malformed("'mappings' is not a string"),
+            (None, Some(Value::Array(sections))) => self.check_sections(sections, dir, depth),
+            (None, Some(_)) => self.malformed("'sections' is not an array"),
+            (Some(_), Some(_)) => self.malformed("map has both 'mappings' and 'sections'"),
+            (None, None) => self.malformed("map has neither 'mappings' nor 'sections'"),
+        }
+    }
+
+    /// Length of an optional array of strings (or `null`s), reporting other shapes.
+    fn string_array_len(&mut self, value: Option<&Value>, field: &str) -> usize {
+        match value {
+            None => 0,
+            Some(Value::Array(items)) => {
+                if items.iter().any(|item| !item.is_string() && !item.is_null()) {
+                    self.malformed(format!("'{}' contains non-string entries", field));
+                }
+                items.len()
+            }
+            Some(_) => {
+                self.malformed(format!("'{}' is not an array", field));
+                0
+            }
+        }
+    }
+
+    fn check_sections(&mut self, sections: &[Value], dir: &Path, depth: usize) {
+        if depth >= MAX_SECTION_DEPTH {
+            return self.malformed(format!("index map sections nested deeper than {} levels", MAX_SECTION_DEPTH));
+        }
+        let parent = self.section.clone();
+        let mut previous: Option<u64> = None;
+        for (i, section) in sections.iter().enumerate() {
+            self.section = if parent.is_empty() {
+                format!("sections[{}]", i)
+            } else {
+                format!("{}.sections[{}]", parent, i)
+            };
+            let line = section.pointer("/offset/line").and_then(Value::as_u64);
+            let column = section.pointer("/offset/column").and_then(Value::as_u64);
+            match (line, column) {
+                (Some(0), Some(column)) => {
+                    match previous {
+                        Some(previous) if column < previous => {
+                            self.report(None, ProblemKind::Unsorted { offset: column as i64, previous: previous as i64 });
+                        }
+                        Some(previous) if column == previous => {
+                            self.malformed(format!("section starts at offset {:#x} like the previous one", column));
+                        }
+                        _ => {}
+                    }
+                    previous = Some(column);
+                }
+                (Some(line), Some(_)) => {
+                    self.malformed(format!("section starts at generated line {}, but Wasm source maps have a single line", line));
+                }
+                _ => self.malformed("section has no valid 'offset' {line, column}"),
+            }
+            match (section.get("map"), section.get("url").and_then(Value::as_str)) {
+                (Some(map), None) => self.check_map(map, dir, depth + 1),
+                (None, Some(url)) => {
+                    let path = dir.join(url.strip_prefix("file://").unwrap_or(url));
+                    match fs::read_to_string(&path) {
+                        Ok(data) => match serde_json::from_str::<Value>(&data) {
+                            Ok(map) => self.check_map(&map, path.parent().unwrap_or(Path::new("")), depth + 1),
+                            Err(e) => self.malformed(format!("invalid JSON in '{}': {}", path.display(), e)),
+                        },
+                        Err(e) => self.malformed(format!("failed to read '{}': {}", path.display(), e)),
+                    }
+                }
+                (Some(_), Some(_)) => self.malformed("section has both 'map' and 'url'"),
+                (None, None) => self.malformed("section has neither 'map' nor 'url'"),
+            }
+        }
+        self.section = parent;
+    }
+
+    fn check_mappings(&mut self, mappings: &str, sources: usize, names: usize) {
+        let mut gen_offset = Field::default();
+        let mut source_index = Field::default();
+        let mut original_line = Field::default();
+        let mut original_column = Field::default();
+        let mut name_index = Field::default();
+
+        let mut column = 0;
+        for (l, line) in mappings.split(';').enumerate() {
+            if line.is_empty() {
+                column += 1;
+                continue;
+            }
+            for (s, segment) in line.split(',').enumerate() {
+                let pos = SegmentPosition { line: l + 1, segment: s + 1, column };
+                column += segment.len() + 1;
+                let fields = match vlq_decode(segment) {
+                    Ok(fields) => fields,
+                    Err(e) => {
+                        self.report(Some(SegmentPosition { column: pos.column + e.position(), ..pos }), ProblemKind::Vlq(e));
+                        continue;
+                    }
+                };
+                if !matches!(fields.len(), 1 | 4 | 5) {
+                    self.report(Some(pos), ProblemKind::FieldCount(fields.len()));
+                    continue;
+                }
+
+                let previous = gen_offset.value;
+                if let Some(offset) = gen_offset.add(fields[0]) {
+                    if offset < 0 {
+                        self.report(Some(pos), ProblemKind::Negative { field: "generated offset", value: offset });
+                    } else if offset > u32::MAX as i64 {
+                        self.report(Some(pos), ProblemKind::OutOfRange {
+                            field: "generated offset", value: offset, limit: u32::MAX as i64 + 1,
+                        });
+                    } else if offset < previous {
+                        self.report(Some(pos), ProblemKind::Unsorted { offset, previous });
+                    }
+                }
+                if fields.len() >= 4 {
+                    if let Some(index) = source_index.add(fields[1]) {
+                        self.check_index(pos, "source index", index, sources);
+                    }
+                    if let Some(line) = original_line.add(fields[2]) {
+                        self.check_non_negative(pos, "original line", line);
+                    }
+                    if let Some(column) = original_column.add(fields[3]) {
+                        self.check_non_negative(pos, "original column", column);
+                    }
+                }
+                if fields.len() == 5
+                    && let Some(index) = name_index.add(fields[4])
+                {
+                    self.check_index(pos, "name index", index, names);
+                }
+            }
+        }
+    }
+
+    fn check_non_negative(&mut self, pos: SegmentPosition, field: &'static str, value: i64) {
+        if value < 0 {
+            self.report(Some(pos), ProblemKind::Negative { field, value });
+        }
+    }
+
+    fn check_index(&mut self, pos: SegmentPosition, field: &'static str, value: i64, len: usize) {
+        if value < 0 {
+            self.report(Some(pos), ProblemKind::Negative { field, value });
+        } else if value >= len as i64 {
+            self.report(Some(pos), ProblemKind::OutOfRange { field, value, limit: len as i64 });
+        }
+    }
+}
+
+/// A running, delta-encoded segment field.
+#[derive(Default)]
+struct Field {
+    value: i64,
+    used: bool,
+}
+
+impl Field {
+    /// Apply `delta`, returning the new value if it needs checking: on first
+    /// use or when it changed, so a bad value is reported only where it arises.
+    fn add(&mut self, delta: i64) -> Option<i64> {
+        self.value += delta;
+        let check = delta != 0 || !self.used;
+        self.used = true;
+        check.then_some(self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vlq::vlq_encode;
+
+    /// Problems of a map with sources `a.ts`, names `f` and the given mappings,
+    /// as (line, segment, char, kind).
+    fn problems(mappings: &str) -> Vec<(usize, usize, usize, ProblemKind)> {
+        let json = format!(r#"{{"version":3,"sources":["a.ts"],"names":["f"],"mappings":"{}"}}"#, mappings);
+        validate(&json, None).into_iter()
+            .map(|p| {
+                assert_eq!(p.section, "");
+                let pos = p.position.expect("a position");
+                (pos.line, pos.segment, pos.column, p.kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_mappings() {
+        assert_eq!(problems("AAAAA,CACA;;K,CAAA"), []);
+    }
+
+    #[test]
+    fn field_count() {
+        assert_eq!(problems("AAAA,AA"), [(1, 2, 5, ProblemKind::FieldCount(2))]);
+        assert_eq!(problems(";;AAAA,AAAAAA"), [(3, 2, 7, ProblemKind::FieldCount(6))]);
+    }
+
+    #[test]
+    fn negative() {
+        assert_eq!(problems("AAAA;D"), [(2, 1, 5, ProblemKind::Negative { field: "generated offset", value: -1 })]);
+        assert_eq!(problems("AAAA,CADA"), [(1, 2, 5, ProblemKind::Negative { field: "original line", value: -1 })]);
+        assert_eq!(problems("AAAD"), [(1, 1, 0, ProblemKind::Negative { field: "original column", value: -1 })]);
+        assert_eq!(problems("ADAA"), [(1, 1, 0, ProblemKind::Negative { field: "source index", value: -1 })]);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(problems("AAAA,ACAA"), [
+            (1, 2, 5, ProblemKind::OutOfRange { field: "source index", value: 1, limit: 1 }),
+        ]);
+        assert_eq!(problems("AAAAC"), [(1, 1, 0, ProblemKind::OutOfRange { field: "name index", value: 1, limit: 1 })]);
+        // offsets i32::MAX, u32::MAX - 1, then 2^32
+        let mut mappings = String::new();
+        for delta in [i32::MAX as i64, i32::MAX as i64, 2] {
+            if !mappings.is_empty() {
+                mappings.push(',');
+            }
+            vlq_encode(delta, &mut mappings);
+        }
+        let column = mappings.rfind(',').unwrap() + 1;
+        assert_eq!(problems(&mappings), [
+            (1, 3, column, ProblemKind::OutOfRange { field: "generated offset", value: 1 << 32, limit: 1 << 32 }),
+        ]);
+    }
+
+    #[test]
+    fn bad_index_reported_only_where_it_arises() {
+        assert_eq!(problems("ACAA,CAAA,CAAA"), [
+            (1, 1, 0, ProblemKind::OutOfRange { field: "source index", value: 1, limit: 1 }),
+        ]);
+    }
+
+    #[test]
+    fn unsorted() {
+        assert_eq!(problems("K,F"), [(1, 2, 2, ProblemKind::Unsorted { offset: 3, previous: 5 })]);
+        assert_eq!(problems("K;F"), [(2, 1, 2, ProblemKind::Unsorted { offset: 3, previous: 5 })]);
+    }
+
+    #[test]
+    fn invalid_char() {
+        assert_eq!(problems("AAAA,A!AA"), [
+            (1, 2, 6, ProblemKind::Vlq(VlqError::InvalidChar { ch: '!', position: 1 })),
+        ]);
+    }
+
+    #[test]
+    fn sections_must_start_after_each_other() {
+        let map = r#"{"version":3,"sources":["a.ts"],"mappings":"AAAA"}"#;
+        let index = |a: u32, b: u32| format!(
+            r#"{{"version":3,"sections":[{{"offset":{{"line":0,"column":{}}},"map":{}}},{{"offset":{{"line":0,"column":{}}},"map":{}}}]}}"#,
+            a, map, b, map,
+        );
+        assert_eq!(validate(&index(0, 16), None), []);
+        let problems = validate(&index(16, 0), None);
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].section.as_str(), &problems[0].kind), ("sections[1]", &ProblemKind::Unsorted { offset: 0, previous: 16 }));
+        let problems = validate(&index(16, 16), None);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0].kind, ProblemKind::Malformed(_)));
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(problems("AAAA,AAAg"), [(1, 2, 9, ProblemKind::Vlq(VlqError::Truncated { position: 4 }))]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Base64 VLQ encoding and decoding as used by the `mappings` field of v3 source maps.
+
+use std::fmt;
+
+/// Largest magnitude of a decoded value. Generated columns of Wasm maps are
+/// byte offsets, so deltas span the whole `u32` range.
+pub const VLQ_MAX: i64 = u32::MAX as i64;
+
+/// Digits needed for a sign bit and 32 bits of magnitude, 5 bits each.
+const MAX_DIGITS: u32 = 7;
+
+/// Why a segment could not be decoded. Positions are byte offsets into the segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VlqError {
+    /// A character outside the base64 alphabet.
+    InvalidChar { ch: char, position: usize },
+    /// The segment ends with a continuation digit.
+    Truncated { position: usize },
+    /// The value starting at `position` has a magnitude above [`VLQ_MAX`].
+    Overflow { position: usize },
+}
+
+impl VlqError {
+    pub fn position(&self) -> usize {
+        match *self {
+            VlqError::InvalidChar { position, .. }
+            | VlqError::Truncated { position }
+            | VlqError::Overflow { position } => position,
+        }
+    }
+}
+
+impl fmt::Display for VlqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VlqError::InvalidChar { ch, .. } => write!(f, "invalid base64 character {:?}", ch),
+            VlqError::Truncated { .. } => write!(f, "segment ends with a continuation bit set"),
+            VlqError::Overflow { .. } => write!(f, "VLQ value overflows 32 bits"),
+        }
+    }
+}
+
+impl std::error::Error for VlqError {}
+
+/// Decode one comma-separated `mappings` segment into its signed fields.
+pub fn vlq_decode(segment: &str) -> Result<Vec<i64>, VlqError> {
+    let mut result = Vec::new();
+    let mut value = 0u64;
+    let mut shift = 0;
+    let mut start = 0;
+    for (position, c) in segment.char_indices() {
+        let digit = match c {
+            'A'..='Z' => c as u64 - 'A' as u64,
+            'a'..='z' => c as u64 - 'a' as u64 + 26,
+            '0'..='9' => c as u64 - '0' as u64 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => return Err(VlqError::InvalidChar { ch: c, position }),
+        };
+        if shift == 0 {
+            start = position;
+        }
+        if shift == 5 * MAX_DIGITS {
+            return Err(VlqError::Overflow { position: start });
+        }
+        value |= (digit & 31) << shift;
+        shift += 5;
+        if digit & 32 == 0 {
+            let magnitude = (value >> 1) as i64;
+            if magnitude > VLQ_MAX {
+                return Err(VlqError::Overflow { position: start });
+            }
+            result.push(if value & 1 != 0 { -magnitude } else { magnitude });
+            value = 0;
+            shift = 0;
+        }
+    }
+    if shift != 0 {
+        return Err(VlqError::Truncated { position: segment.len() });
+    }
+    Ok(result)
+}
+
+/// Append the base64 VLQ encoding of `value` to `out`.
+///
+/// `value` must be within `-VLQ_MAX..=VLQ_MAX` to be decodable again.
+pub fn vlq_encode(value: i64, out: &mut String) {
+    const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    debug_assert!(value.unsigned_abs() <= VLQ_MAX as u64, "VLQ value {} out of range", value);
+    let mut vlq = (value.unsigned_abs() << 1) | (value < 0) as u64;
+    loop {
+        let mut digit = vlq & 31;
+        vlq >>= 5;
+        if vlq != 0 {
+            digit |= 32;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
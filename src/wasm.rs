@@ -0,0 +1,261 @@
+//! Minimal reading of WebAssembly modules: the pieces needed to find and use
+//! their source maps.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use wasmparser::{BinaryReader, ExternalKind, KnownCustom, Name, Parser, Payload, TypeRef};
+
+/// The `\0asm` magic at the start of every WebAssembly binary.
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+
+/// Whether `bytes` look like a WebAssembly binary.
+pub fn is_wasm(bytes: &[u8]) -> bool {
+    bytes.starts_with(WASM_MAGIC)
+}
+
+/// A function defined in the module's code section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    /// Index in the function index space, i.e. counting imported functions first.
+    pub index: u32,
+    /// Debug name from the `name` section, or else the name it is exported as.
+    pub name: Option<String>,
+    /// Module-absolute byte range of the body, excluding its size prefix.
+    /// Function-relative offsets are measured from `body.start`.
+    pub body: Range<u32>,
+}
+
+/// A WebAssembly module read from disk or memory.
+#[derive(Debug, Clone)]
+pub struct WasmModule {
+    bytes: Vec<u8>,
+    path: Option<PathBuf>,
+    source_mapping_url: Option<String>,
+    code_section: Option<Range<u32>>,
+    imported_functions: u32,
+    functions: Vec<Function>,
+    /// Names and content ranges of the custom sections.
+    custom_sections: Vec<(String, Range<u32>)>,
+}
+
+impl WasmModule {
+    /// Parse a module from its binary encoding.
+    pub fn parse(bytes: Vec<u8>) -> Result<WasmModule> {
+        if !is_wasm(&bytes) {
+            anyhow::bail!("Not a WebAssembly binary (missing \\0asm magic)");
+        }
+        let mut source_mapping_url = None;
+        let mut code_section = None;
+        let mut imported_functions = 0u32;
+        let mut bodies: Vec<Range<u32>> = Vec::new();
+        let mut debug_names: HashMap<u32, String> = HashMap::new();
+        let mut export_names: HashMap<u32, String> = HashMap::new();
+        let mut custom_sections: Vec<(String, Range<u32>)> = Vec::new();
+
+        for payload in Parser::new(0).parse_all(&bytes) {
+            match payload.context("Malformed WebAssembly module")? {
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        if matches!(import?.ty, TypeRef::Func(_) | TypeRef::FuncExact(_)) {
+                            imported_functions += 1;
+                        }
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if export.kind == ExternalKind::Func {
+                            export_names.entry(export.index).or_insert_with(|| export.name.to_string());
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => {
+                    code_section = Some(range.start as u32..range.end as u32);
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let range = body.range();
+                    bodies.push(range.start as u32..range.end as u32);
+                }
+                Payload::CustomSection(reader) => {
+                    let start = reader.data_offset() as u32;
+                    custom_sections.push((reader.name().to_string(), start..start + reader.data().len() as u32));
+                    match reader.as_known() {
+                        KnownCustom::Name(names) => {
+                            // a malformed name section only costs us the names
+                            for name in names.into_iter().flatten() {
+                                if let Name::Function(map) = name {
+                                    for naming in map.into_iter().flatten() {
+                                        debug_names.insert(naming.index, naming.name.to_string());
+                                    }
+                                }
+                            }
+                        }
+                        _ if reader.name() == "sourceMappingURL" => {
+                            let mut data = BinaryReader::new(reader.data(), reader.data_offset());
+                            let url = data.read_string().context("Malformed sourceMappingURL section")?;
+                            source_mapping_url = Some(url.to_string());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let functions = bodies.into_iter().enumerate().map(|(i, body)| {
+            let index = imported_functions + i as u32;
+            let name = debug_names.remove(&index).or_else(|| export_names.remove(&index));
+            Function { index, name, body }
+        }).collect();
+
+        Ok(WasmModule {
+            bytes,
+            path: None,
+            source_mapping_url,
+            code_section,
+            imported_functions,
+            functions,
+            custom_sections,
+        })
+    }
+
+    /// Read and parse a module file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<WasmModule> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .with_context(|| format!("Failed to read wasm file '{}'", path.display()))?;
+        let mut module = WasmModule::parse(bytes)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+        module.path = Some(path.to_path_buf());
+        Ok(module)
+    }
+
+    /// The raw module bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The file this module was read from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The URL from the `sourceMappingURL` custom section, if present.
+    pub fn source_mapping_url(&self) -> Option<&str> {
+        self.source_mapping_url.as_deref()
+    }
+
+    /// Module-absolute byte range of the code section contents.
+    pub fn code_section(&self) -> Option<Range<u32>> {
+        self.code_section.clone()
+    }
+
+    /// Number of imported functions, i.e. the index of the first defined function.
+    pub fn imported_functions(&self) -> u32 {
+        self.imported_functions
+    }
+
+    /// Functions defined in the code section, in index (and offset) order.
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// The defined function with the given function index.
+    pub fn function(&self, index: u32) -> Option<&Function> {
+        let i = index.checked_sub(self.imported_functions)?;
+        self.functions.get(i as usize)
+    }
+
+    /// The function whose body contains the module-absolute `offset`.
+    pub fn function_at(&self, offset: u32) -> Option<&Function> {
+        let i = self.functions.partition_point(|f| f.body.start <= offset).checked_sub(1)?;
+        let f = &self.functions[i];
+        f.body.contains(&offset).then_some(f)
+    }
+
+    /// Contents of the first custom section named `name`, e.g. `.debug_line`.
+    pub fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        self.custom_sections.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, range)| &self.bytes[range.start as usize..range.end as usize])
+    }
+}
+
+/// Hand-assembled modules for unit tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::WasmModule;
+
+    fn leb(mut n: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            match n {
+                0 => return out.push(byte),
+                _ => out.push(byte | 0x80),
+            }
+        }
+    }
+
+    /// The content of a `sourceMappingURL` section pointing at `url`.
+    pub(crate) fn source_mapping_url(url: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        leb(url.len(), &mut content);
+        content.extend_from_slice(url.as_bytes());
+        content
+    }
+
+    fn section(id: u8, content: &[u8], out: &mut Vec<u8>) {
+        out.push(id);
+        leb(content.len(), out);
+        out.extend_from_slice(content);
+    }
+
+    /// A module of `() -> ()` functions: one imported from `env` per name of
+    /// `imports`, then one defined per entry of `bodies`, each the instructions
+    /// of a body without locals, ending with `end`.
+    pub(crate) fn module(imports: &[&str], bodies: &[&[u8]]) -> WasmModule {
+        module_with_custom(imports, bodies, &[])
+    }
+
+    /// Like [`module`], with custom sections of the given names and contents
+    /// after the code section.
+    pub(crate) fn module_with_custom(imports: &[&str], bodies: &[&[u8]], custom: &[(&str, &[u8])]) -> WasmModule {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        section(1, &[1, 0x60, 0, 0], &mut bytes);
+        if !imports.is_empty() {
+            let mut content = Vec::new();
+            leb(imports.len(), &mut content);
+            for name in imports {
+                content.extend_from_slice(b"\x03env");
+                leb(name.len(), &mut content);
+                content.extend_from_slice(name.as_bytes());
+                content.extend_from_slice(&[0, 0]);
+            }
+            section(2, &content, &mut bytes);
+        }
+        let mut functions = Vec::new();
+        leb(bodies.len(), &mut functions);
+        functions.extend(bodies.iter().map(|_| 0));
+        section(3, &functions, &mut bytes);
+        let mut code = Vec::new();
+        leb(bodies.len(), &mut code);
+        for body in bodies {
+            leb(body.len() + 1, &mut code);
+            code.push(0);
+            code.extend_from_slice(body);
+        }
+        section(10, &code, &mut bytes);
+        for (name, data) in custom {
+            let mut content = Vec::new();
+            leb(name.len(), &mut content);
+            content.extend_from_slice(name.as_bytes());
+            content.extend_from_slice(data);
+            section(0, &content, &mut bytes);
+        }
+        WasmModule::parse(bytes).unwrap()
+    }
+}
@@ -0,0 +1,57 @@
+//! Round trips of decoded entries through the map serializer.
+
+use proptest::prelude::*;
+use wasm_map_lookup::{MappingEntry, SourceMap};
+
+const SOURCES: [&str; 3] = ["assembly/index.ts", "assembly/util.ts", "~lib/rt/tlsf.ts"];
+const NAMES: [&str; 3] = ["calculate", "process", "main"];
+
+fn entry() -> impl Strategy<Value = MappingEntry> {
+    let mapped = (any::<u32>(), 0..SOURCES.len(), 1..=u32::MAX, any::<u32>(), prop::option::of(0..NAMES.len()))
+        .prop_map(|(gen_offset, source, line, column, name)| MappingEntry {
+            gen_offset,
+            source: Some(SOURCES[source].to_string()),
+            line: Some(line),
+            column: Some(column),
+            name: name.map(|n| NAMES[n].to_string()),
+        });
+    let unmapped = any::<u32>().prop_map(|gen_offset| MappingEntry {
+        gen_offset,
+        source: None,
+        line: None,
+        column: None,
+        name: None,
+    });
+    prop_oneof![3 => mapped, 1 => unmapped]
+}
+
+proptest! {
+    #[test]
+    fn decode_of_encode_is_identity(mut entries in prop::collection::vec(entry(), 1..64)) {
+        let json = SourceMap::from_entries(entries.clone()).to_json().unwrap();
+        let decoded = SourceMap::parse(&json).unwrap();
+        entries.sort_by_key(|e| e.gen_offset);
+        prop_assert_eq!(decoded.entries(), &entries[..]);
+    }
+}
+
+#[test]
+fn rewritten_map_keeps_everything_but_the_paths() {
+    let json = r#"{
+        "version": 3,
+        "file": "program.wasm",
+        "sources": ["assembly/index.ts", "~lib/builtins.ts"],
+        "sourcesContent": ["export function f(): i32 {\n  return 1;\n}\n", null],
+        "names": ["f", "g"],
+        "mappings": "iBAAA,GAAAA,EACE,CAAAC,KCAA,C"
+    }"#;
+    let mut sm = SourceMap::parse(json).unwrap();
+    sm.map_sources(|s| s.replace("assembly/", "src/"));
+    let written = SourceMap::parse(&sm.to_json().unwrap()).unwrap();
+
+    assert_eq!(written.file, sm.file);
+    assert_eq!(written.sources, ["src/index.ts", "~lib/builtins.ts"]);
+    assert_eq!(written.sources_content, sm.sources_content);
+    assert_eq!(written.names, sm.names);
+    assert_eq!(written.entries(), sm.entries());
+}
@@ -0,0 +1,87 @@
+//! Property tests of the VLQ codec against a straightforward reference encoder.
+
+use proptest::prelude::*;
+use wasm_map_lookup::vlq::{vlq_decode, vlq_encode, VlqError, VLQ_MAX};
+use wasm_map_lookup::SourceMap;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `value` as in the source map spec: sign in the lowest bit, then
+/// 5-bit groups, least significant first, with bit 6 marking continuation.
+fn reference_encode(value: i64, out: &mut String) {
+    let mut vlq = (value.unsigned_abs() << 1) | (value < 0) as u64;
+    loop {
+        let mut digit = vlq & 31;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 32;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_all(values: &[i64]) -> String {
+    let mut out = String::new();
+    for &v in values {
+        reference_encode(v, &mut out);
+    }
+    out
+}
+
+proptest! {
+    #[test]
+    fn decodes_what_the_reference_encodes(values in prop::collection::vec(-VLQ_MAX..=VLQ_MAX, 0..8)) {
+        prop_assert_eq!(vlq_decode(&encode_all(&values)), Ok(values));
+    }
+
+    #[test]
+    fn encodes_like_the_reference(value in -VLQ_MAX..=VLQ_MAX) {
+        let (mut ours, mut reference) = (String::new(), String::new());
+        vlq_encode(value, &mut ours);
+        reference_encode(value, &mut reference);
+        prop_assert_eq!(ours, reference);
+    }
+
+    #[test]
+    fn rejects_values_past_the_limit(value in (VLQ_MAX + 1)..=i64::MAX >> 1, negative: bool) {
+        let value = if negative { -value } else { value };
+        let segment = encode_all(&[0, value]);
+        prop_assert_eq!(vlq_decode(&segment), Err(VlqError::Overflow { position: 1 }));
+    }
+
+    #[test]
+    fn never_panics_on_arbitrary_input(segment in "[A-Za-z0-9+/!-]{0,40}") {
+        let _ = vlq_decode(&segment);
+    }
+
+    #[test]
+    fn rejects_truncated_values(values in prop::collection::vec(-VLQ_MAX..=VLQ_MAX, 1..8)) {
+        let mut segment = encode_all(&values);
+        // the last digit of a multi-digit value carries no continuation bit
+        segment.push('g');
+        prop_assert_eq!(vlq_decode(&segment), Err(VlqError::Truncated { position: segment.len() }));
+    }
+}
+
+#[test]
+fn reports_invalid_characters() {
+    assert_eq!(vlq_decode("AA!A"), Err(VlqError::InvalidChar { ch: '!', position: 2 }));
+}
+
+#[test]
+fn decodes_the_extremes() {
+    assert_eq!(vlq_decode("+/////H"), Ok(vec![VLQ_MAX]));
+    assert_eq!(vlq_decode("//////H"), Ok(vec![-VLQ_MAX]));
+}
+
+#[test]
+fn rejects_offsets_leaving_the_u32_range() {
+    let map = |mappings: &str| format!(r#"{{"version":3,"sources":["a.ts"],"mappings":"{}"}}"#, mappings);
+    assert!(SourceMap::parse(&map("CAAA,FAAA")).is_err());
+    assert!(SourceMap::parse(&map("+/////HAAA,CAAA")).is_err());
+    let sm = SourceMap::parse(&map("+/////HAAA")).unwrap();
+    assert_eq!(sm.entries()[0].gen_offset, u32::MAX);
+}